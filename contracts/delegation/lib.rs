@@ -1,23 +1,48 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
-pub use self::delegation::Delegation;
+pub use self::delegation::{Delegation, Error, Result};
 
 use ink_lang as ink;
 
 #[ink::contract]
 pub mod delegation {
-    use epoch_proxy::EpochProxy;
+    use directory::{resolve, Directory, EPOCH, REGISTRY};
+    use epoch::Epoch;
     use ink_env;
     use ink_lang::EmitEvent;
     use ink_prelude::collections::BTreeMap;
     use ink_storage::collections::HashMap as StorageHashMap;
-    use registry_proxy::RegistryProxy;
+    use registry::Registry;
+
+    /// Errors that can occur upon calling this contract.
+    #[cfg_attr(feature = "std", derive(::scale_info::TypeInfo))]
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, scale::Encode, scale::Decode)]
+    pub enum Error {
+        /// Name is not registered or expired.
+        NameNotFound,
+        /// Name has no delegations.
+        DelegateNotFound,
+        /// Account did not delegate to the name.
+        InvestorNotFound,
+        /// Minimum delegation period was not reached yet.
+        MinThresholdNotMet,
+        /// Epoch schedule did not start yet.
+        BeforeGenesis,
+        /// Transfer would have brought the contract below subsistence threshold.
+        BelowSubsistenceThreshold,
+        /// Transfer of funds failed.
+        TransferFailed,
+        /// Component is not set in the directory.
+        ComponentNotFound,
+    }
+
+    /// Type alias for the contract's result type.
+    pub type Result<T> = core::result::Result<T, Error>;
 
     #[ink(storage)]
     pub struct Delegation {
-        delegates: StorageHashMap<AccountId, BTreeMap<AccountId, (Balance, BlockNumber)>>,
-        registry: RegistryProxy,
-        epoch: EpochProxy,
+        delegates: StorageHashMap<Hash, BTreeMap<AccountId, (Balance, BlockNumber)>>,
+        directory: Directory,
     }
 
     /// Emitted whenever a new Link is being registered.
@@ -44,35 +69,51 @@ pub mod delegation {
     impl Delegation {
         /// Initializes the value to the initial value.
         #[ink(constructor)]
-        pub fn new(init_value: RegistryProxy, epoch: EpochProxy) -> Self {
+        pub fn new(directory: Directory) -> Self {
             Self {
-                registry: init_value,
-                epoch,
+                directory,
                 delegates: Default::default(),
             }
         }
 
-        fn not_expired(&self, hash_name: &Hash) -> Option<(AccountId, BlockNumber, u32)> {
-            self.registry.get().not_expired(hash_name.clone())
+        fn epoch(&self) -> Result<Epoch> {
+            resolve(&self.directory, EPOCH).ok_or(Error::ComponentNotFound)
+        }
+
+        fn registry(&self) -> Result<Registry> {
+            resolve(&self.directory, REGISTRY).ok_or(Error::ComponentNotFound)
+        }
+
+        fn not_expired(&self, hash_name: &Hash) -> Result<Option<(AccountId, BlockNumber, u32)>> {
+            Ok(self.registry()?.not_expired(hash_name.clone()))
         }
 
         fn get_min_threshold(&self) -> BlockNumber {
             BlockNumber::from(10u32)
         }
 
-        fn get_current_epoch(&self) -> u32 {
-            self.epoch.get().get_current_epoch()
+        fn get_current_epoch(&self) -> Result<u32> {
+            self.epoch()?
+                .get_current_epoch()
+                .ok_or(Error::BeforeGenesis)
+        }
+
+        fn transfer_balance(&self, to: AccountId, value: Balance) -> Result<()> {
+            self.env().transfer(to, value).map_err(|err| match err {
+                ink_env::Error::BelowSubsistenceThreshold => Error::BelowSubsistenceThreshold,
+                _ => Error::TransferFailed,
+            })
         }
 
         #[ink(message, payable, selector = 0xBABEBABE)]
-        pub fn delegate(&mut self, name: Hash, from: AccountId) {
+        pub fn delegate(&mut self, name: Hash, from: AccountId) -> Result<()> {
             let payment = self.env().transferred_balance();
             ink_env::debug_println!("delegate payment: {}", payment);
-            if let Some((ref o, _b, _d)) = self.not_expired(&name) {
-                let epoch = self.get_current_epoch();
+            if let Some((_o, _b, _d)) = self.not_expired(&name)? {
+                let epoch = self.get_current_epoch()?;
                 // let bn = self.env().block_number();
                 self.delegates
-                    .entry(o.clone())
+                    .entry(name.clone())
                     .and_modify(|old_value_map| {
                         old_value_map
                             .entry(from.clone())
@@ -98,89 +139,58 @@ pub mod delegation {
                         value: payment.into(),
                     },
                 );
+                Ok(())
             } else {
                 ink_env::debug_println!("name not found");
-                panic!("name not found");
+                Err(Error::NameNotFound)
             }
         }
 
         #[ink(message)]
-        pub fn undelegate(&mut self, name: Hash) {
+        pub fn undelegate(&mut self, name: Hash) -> Result<()> {
             let caller = self.env().caller();
-            let epoch = self.get_current_epoch();
+            let epoch = self.get_current_epoch()?;
             // let bn = self.env().block_number();
             let min = self.get_min_threshold();
-            if let Some((ref o, _b, _d)) = self.not_expired(&name) {
-                if let Some(a) = self.delegates.get(&o) {
-                    if let Some(d) = a.get(&caller) {
-                        let p = epoch - d.1;
-                        if p < min {
-                            ink_env::debug_println!(
-                                "min threshold not met: epoch/on/min/p {:?}/{:?}/{:?}/{:?}",
-                                epoch,
-                                d.1,
-                                min,
-                                p
-                            );
-                            panic!("min threshold not met");
-                        }
-                    } else {
-                        ink_env::debug_println!("investor not found");
-                        panic!("investor not found");
-                    }
-                } else {
-                    ink_env::debug_println!("delegate not found");
-                    panic!("delegate not found");
-                }
-                let success = {
-                    if let Some(a) = self.delegates.get(&o) {
-                        if let Some(d) = a.get(&caller) {
-                            let value = (*d).0;
-                            // let value = *d;
-                            match self.env().transfer(caller.clone(), value) {
-                                Err(ink_env::Error::BelowSubsistenceThreshold) => {
-                                    panic!(
-                                        "requested transfer would have brought contract\
-                                        below subsistence threshold!"
-                                    )
-                                }
-                                Err(_) => panic!("transfer failed!"),
-                                Ok(_) => Some(value),
-                            }
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                };
-                if let Some(value) = success {
-                    if let Some(a) = self.delegates.get_mut(&o) {
-                        if let Some(_) = a.remove(&caller) {
-                            EmitEvent::<Self>::emit_event(
-                                self.env(),
-                                Undelegate {
-                                    name,
-                                    from: caller.clone(),
-                                    value,
-                                },
-                            );
-                        } else {
-                            ink_env::debug_println!("failed to remove investor");
-                            panic!("failed to remove investor");
-                        }
-                    } else {
-                        ink_env::debug_println!("operator not found");
-                        panic!("operator not found");
+            if self.not_expired(&name)?.is_none() {
+                ink_env::debug_println!("name not found");
+                return Err(Error::NameNotFound);
+            }
+            let value = if let Some(a) = self.delegates.get(&name) {
+                if let Some(d) = a.get(&caller) {
+                    let p = epoch.saturating_sub(d.1);
+                    if p < min {
+                        ink_env::debug_println!(
+                            "min threshold not met: epoch/on/min/p {:?}/{:?}/{:?}/{:?}",
+                            epoch,
+                            d.1,
+                            min,
+                            p
+                        );
+                        return Err(Error::MinThresholdNotMet);
                     }
+                    (*d).0
                 } else {
-                    ink_env::debug_println!("transfer failed");
-                    panic!("transfer failed");
+                    ink_env::debug_println!("investor not found");
+                    return Err(Error::InvestorNotFound);
                 }
             } else {
-                ink_env::debug_println!("name not found");
-                panic!("name not found");
+                ink_env::debug_println!("delegate not found");
+                return Err(Error::DelegateNotFound);
+            };
+            self.transfer_balance(caller.clone(), value)?;
+            if let Some(a) = self.delegates.get_mut(&name) {
+                let _ = a.remove(&caller);
             }
+            EmitEvent::<Self>::emit_event(
+                self.env(),
+                Undelegate {
+                    name,
+                    from: caller.clone(),
+                    value,
+                },
+            );
+            Ok(())
         }
 
         #[ink(message)]
@@ -189,8 +199,8 @@ pub mod delegation {
             investor: AccountId,
             name: Hash,
         ) -> Option<(Balance, BlockNumber)> {
-            if let Some((ref o, _b, _d)) = self.not_expired(&name) {
-                if let Some(a) = self.delegates.get(&o) {
+            if let Some((_o, _b, _d)) = self.not_expired(&name).ok()? {
+                if let Some(a) = self.delegates.get(&name) {
                     a.get(&investor).cloned()
                 } else {
                     None
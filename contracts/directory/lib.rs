@@ -0,0 +1,205 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use ink_lang as ink;
+
+pub use self::directory::{
+    Directory, Error, Result, DELEGATION, DISPUTE, EPOCH, INDEXER_META, REGISTRY, SUBSCRIPTION,
+};
+
+/// Looks component `id` up in `directory` and wraps its address as the
+/// contract `T`, `None` while no address is set for it.
+pub fn resolve<T>(directory: &Directory, id: u32) -> Option<T>
+where
+    T: ink_env::call::FromAccountId<ink_env::DefaultEnvironment>,
+{
+    directory.get(id).map(T::from_account_id)
+}
+
+#[ink::contract]
+mod directory {
+    use ink_prelude::vec::Vec;
+    use ink_storage::collections::HashMap as StorageHashMap;
+
+    /// Well-known component ids of the DAO.
+    pub const EPOCH: u32 = 1;
+    pub const REGISTRY: u32 = 2;
+    pub const INDEXER_META: u32 = 3;
+    pub const DELEGATION: u32 = 4;
+    pub const SUBSCRIPTION: u32 = 5;
+    pub const DISPUTE: u32 = 6;
+
+    /// Errors that can occur upon calling this contract.
+    #[cfg_attr(feature = "std", derive(::scale_info::TypeInfo))]
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, scale::Encode, scale::Decode)]
+    pub enum Error {
+        /// Caller is not the directory admin.
+        NotAdmin,
+        /// No address is set for the component id.
+        ComponentNotFound,
+    }
+
+    /// Type alias for the contract's result type.
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    /// Maps component ids to the address of the live contract, every DAO
+    /// contract looks its dependencies up here on each call.
+    #[ink(storage)]
+    pub struct Directory {
+        components: StorageHashMap<u32, AccountId>,
+        admin: AccountId,
+    }
+
+    /// Emitted whenever a component is set, replaced or removed.
+    #[ink(event)]
+    pub struct ComponentChanged {
+        #[ink(topic)]
+        by: AccountId,
+        #[ink(topic)]
+        id: u32,
+        old: Option<AccountId>,
+        new: Option<AccountId>,
+    }
+
+    impl Directory {
+        /// Creates an empty directory, the caller becomes the admin.
+        #[ink(constructor)]
+        pub fn new() -> Self {
+            Self {
+                components: Default::default(),
+                admin: Self::env().caller(),
+            }
+        }
+
+        fn ensure_admin(&self) -> Result<AccountId> {
+            let caller = self.env().caller();
+            if caller != self.admin {
+                return Err(Error::NotAdmin);
+            }
+            Ok(caller)
+        }
+
+        /// Returns the address of component `id`, if any.
+        #[ink(message)]
+        pub fn get(&self, id: u32) -> Option<AccountId> {
+            self.components.get(&id).cloned()
+        }
+
+        /// Returns every `(id, address)` pair, ordered by id.
+        #[ink(message)]
+        pub fn components(&self) -> Vec<(u32, AccountId)> {
+            let mut components: Vec<(u32, AccountId)> = self
+                .components
+                .iter()
+                .map(|(id, address)| (*id, *address))
+                .collect();
+            components.sort_unstable_by_key(|(id, _)| *id);
+            components
+        }
+
+        /// Points component `id` to `address`, replacing the previous one.
+        #[ink(message)]
+        pub fn set(&mut self, id: u32, address: AccountId) -> Result<()> {
+            let caller = self.ensure_admin()?;
+            let old = self.components.insert(id, address);
+            self.env().emit_event(ComponentChanged {
+                by: caller,
+                id,
+                old,
+                new: Some(address),
+            });
+            Ok(())
+        }
+
+        /// Removes component `id`, contracts depending on it fail until it
+        /// is set again.
+        #[ink(message)]
+        pub fn remove(&mut self, id: u32) -> Result<()> {
+            let caller = self.ensure_admin()?;
+            let old = self.components.take(&id).ok_or(Error::ComponentNotFound)?;
+            self.env().emit_event(ComponentChanged {
+                by: caller,
+                id,
+                old: Some(old),
+                new: None,
+            });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn admin(&self) -> AccountId {
+            self.admin
+        }
+
+        #[ink(message)]
+        pub fn set_admin(&mut self, new_admin: AccountId) -> Result<()> {
+            self.ensure_admin()?;
+            self.admin = new_admin;
+            Ok(())
+        }
+    }
+
+    /// Unit tests in Rust are normally defined within such a `#[cfg(test)]`
+    /// module and test functions are marked with a `#[test]` attribute.
+    /// The below code is technically just normal Rust code.
+    #[cfg(test)]
+    mod tests {
+        /// Imports all the definitions from the outer scope so we can use them here.
+        use super::*;
+
+        /// Imports `ink_lang` so we can use `#[ink::test]`.
+        use ink_lang as ink;
+
+        /// We test if the constructor does its job.
+        #[ink::test]
+        fn default_works() {
+            let accounts = default_accounts();
+            let directory = Directory::new();
+            assert_eq!(directory.admin(), accounts.alice);
+            assert_eq!(directory.get(EPOCH), None);
+            assert!(directory.components().is_empty());
+        }
+
+        /// Only the admin changes components, listing is ordered by id.
+        #[ink::test]
+        fn set_and_remove_components() {
+            let accounts = default_accounts();
+            let mut directory = Directory::new();
+
+            set_sender(accounts.bob);
+            assert_eq!(directory.set(EPOCH, accounts.eve), Err(Error::NotAdmin));
+            assert_eq!(directory.remove(EPOCH), Err(Error::NotAdmin));
+
+            set_sender(accounts.alice);
+            assert_eq!(directory.set(REGISTRY, accounts.django), Ok(()));
+            assert_eq!(directory.set(EPOCH, accounts.eve), Ok(()));
+            assert_eq!(directory.set(EPOCH, accounts.frank), Ok(()));
+            assert_eq!(directory.get(EPOCH), Some(accounts.frank));
+            assert_eq!(
+                directory.components(),
+                vec![(EPOCH, accounts.frank), (REGISTRY, accounts.django)]
+            );
+
+            assert_eq!(directory.remove(EPOCH), Ok(()));
+            assert_eq!(directory.remove(EPOCH), Err(Error::ComponentNotFound));
+            assert_eq!(directory.get(EPOCH), None);
+            assert_eq!(ink_env::test::recorded_events().count(), 4);
+        }
+
+        fn set_sender(sender: AccountId) {
+            let callee =
+                ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                sender,
+                callee,
+                1000000,
+                1000000,
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])), // dummy
+            );
+        }
+
+        fn default_accounts() -> ink_env::test::DefaultAccounts<ink_env::DefaultEnvironment> {
+            ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Off-chain environment should have been initialized already")
+        }
+    }
+}
@@ -2,7 +2,7 @@
 
 use ink_lang as ink;
 
-pub use self::dispute::Dispute;
+pub use self::dispute::{Dispute, Error, Result};
 
 #[ink::contract]
 mod dispute {
@@ -12,9 +12,35 @@ mod dispute {
     use ink_prelude::vec::Vec;
     use ink_storage::collections::HashMap as StorageHashMap;
 
-    // use registry_proxy::RegistryProxy;
-    // use epoch_proxy::EpochProxy;
-    use subscription_proxy::{SubscriberData, SubscriptionProxy};
+    use directory::{resolve, Directory, SUBSCRIPTION};
+    use subscription::{SubscriberData, Subscription};
+
+    /// Errors that can occur upon calling this contract.
+    #[cfg_attr(feature = "std", derive(::scale_info::TypeInfo))]
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, scale::Encode, scale::Decode)]
+    pub enum Error {
+        /// Caller is not a judge.
+        NotJudge,
+        /// Caller is not subscribed to the name.
+        NotSubscribed,
+        /// Name has no disputes.
+        NameNotFound,
+        /// Caller has no dispute for the name.
+        DisputeNotFound,
+        /// Caller already has a dispute which did not expire yet.
+        DisputeAlreadyInPlace,
+        /// No active dispute with the given cid.
+        InvalidCid,
+        /// Cid belongs to another dispute or already has a verdict.
+        CidInUse,
+        /// Epoch schedule did not start yet.
+        BeforeGenesis,
+        /// Component is not set in the directory.
+        ComponentNotFound,
+    }
+
+    /// Type alias for the contract's result type.
+    pub type Result<T> = core::result::Result<T, Error>;
 
     /// Defines the storage of your contract.
     /// Add new fields to the below struct in order
@@ -25,7 +51,10 @@ mod dispute {
         reputation: StorageHashMap<Hash, u16>,
         judge: Vec<AccountId>,
         veredict: StorageHashMap<Hash, BTreeMap<AccountId, bool>>,
-        subscription: SubscriptionProxy,
+        /// Verdicts of expired disputes with the name and subscriber which
+        /// raised them, kept once their votes are dropped.
+        decided: StorageHashMap<Hash, (Hash, AccountId, bool)>,
+        directory: Directory,
     }
 
     #[ink(event)]
@@ -51,22 +80,27 @@ mod dispute {
     impl Dispute {
         /// Initializes the value to the initial value.
         #[ink(constructor)]
-        pub fn new(judger: AccountId, subscription: SubscriptionProxy) -> Self {
+        pub fn new(judger: AccountId, directory: Directory) -> Self {
             let mut judgers = Vec::default();
             judgers.push(judger);
             Self {
                 dispute: Default::default(),
                 reputation: Default::default(),
                 veredict: Default::default(),
-                subscription,
+                decided: Default::default(),
+                directory,
                 judge: judgers,
             }
         }
 
-        fn is_owner(&self, hash_name: Hash) -> bool {
-            self.subscription
-                .get()
-                .is_owner_from(hash_name.clone(), self.env().caller().clone())
+        fn subscription(&self) -> Result<Subscription> {
+            resolve(&self.directory, SUBSCRIPTION).ok_or(Error::ComponentNotFound)
+        }
+
+        fn is_owner(&self, hash_name: Hash) -> Result<bool> {
+            Ok(self
+                .subscription()?
+                .is_owner_from(hash_name.clone(), self.env().caller().clone()))
         }
 
         fn is_judge(&self) -> bool {
@@ -75,13 +109,14 @@ mod dispute {
         }
 
         #[ink(message)]
-        pub fn insert_judge(&mut self, judge: AccountId) {
+        pub fn insert_judge(&mut self, judge: AccountId) -> Result<()> {
             let is_judge = self.is_judge();
             if is_judge {
                 self.judge.push(judge);
+                Ok(())
             } else {
                 ink_env::debug_println!("not a judge");
-                panic!("not a judge");
+                Err(Error::NotJudge)
             }
         }
 
@@ -89,10 +124,8 @@ mod dispute {
             &self,
             hash_name: Hash,
             subscriber: AccountId,
-        ) -> Option<SubscriberData> {
-            self.subscription
-                .get()
-                .get_subscription(hash_name, subscriber)
+        ) -> Result<Option<SubscriberData>> {
+            Ok(self.subscription()?.get_subscription(hash_name, subscriber))
         }
 
         fn get_dispute_expiration(&self) -> u32 {
@@ -100,8 +133,14 @@ mod dispute {
         }
 
         #[ink(message)]
-        pub fn get_current_epoch(&self) -> u32 {
-            self.subscription.get().get_current_epoch()
+        pub fn get_current_epoch(&self) -> Option<u32> {
+            self.subscription().ok()?.get_current_epoch()
+        }
+
+        fn current_epoch(&self) -> Result<u32> {
+            self.subscription()?
+                .get_current_epoch()
+                .ok_or(Error::BeforeGenesis)
         }
 
         #[ink(message)]
@@ -109,7 +148,9 @@ mod dispute {
             self.reputation.get(&hash_name).cloned()
         }
 
-        #[ink(message)]
+        /// Returns the cid and start epoch of the dispute `subscriber` raised
+        /// on `hash_name`, `Subscription` checks it on receipt challenges.
+        #[ink(message, selector = 0xDEC1DE01)]
         pub fn get_cid(&self, hash_name: Hash, subscriber: AccountId) -> Option<(Hash, u32)> {
             self.dispute
                 .get(&hash_name)
@@ -120,13 +161,19 @@ mod dispute {
 
         #[ink(message)]
         pub fn cid_exists(&self, cid: Hash) -> bool {
-            let epoch = self.get_current_epoch();
+            match self.get_current_epoch() {
+                Some(epoch) => self.cid_exists_at(cid, epoch),
+                None => false,
+            }
+        }
+
+        fn cid_exists_at(&self, cid: Hash, epoch: u32) -> bool {
             let expire = self.get_dispute_expiration();
             self.dispute
                 .values()
                 .map(|data| {
                     data.values().filter_map(|(ref inner_cid, ref on)| {
-                        if *inner_cid == cid && (epoch - on) < expire {
+                        if *inner_cid == cid && epoch.saturating_sub(*on) < expire {
                             Some(true)
                         } else {
                             None
@@ -138,36 +185,91 @@ mod dispute {
                 .is_some()
         }
 
+        /// Epoch hook, drops disputes older than `get_dispute_expiration`
+        /// together with their votes, keeping only the verdict. Expiry is computed from the current
+        /// epoch and not from `_epoch` so anyone can call it.
+        #[ink(message, selector = 0xE90CBABE)]
+        pub fn on_epoch_started(&mut self, _epoch: u32) -> Result<()> {
+            let epoch = self.current_epoch()?;
+            self.prune_expired_at(epoch);
+            Ok(())
+        }
+
+        fn prune_expired_at(&mut self, epoch: u32) {
+            let expire = self.get_dispute_expiration();
+            let names: Vec<Hash> = self.dispute.keys().cloned().collect();
+            let mut expired = Vec::new();
+            for name in names {
+                if let Some(disputes) = self.dispute.get_mut(&name) {
+                    disputes.retain(|from, (cid, on)| {
+                        let active = epoch.saturating_sub(*on) < expire;
+                        if !active {
+                            expired.push((name, *from, *cid));
+                        }
+                        active
+                    });
+                    if disputes.is_empty() {
+                        let _ = self.dispute.take(&name);
+                    }
+                }
+            }
+            for (name, from, cid) in expired {
+                if let Some(upheld) = self.majority(cid) {
+                    self.decided.insert(cid, (name, from, upheld));
+                }
+                let _ = self.veredict.take(&cid);
+            }
+        }
+
         #[ink(message, selector = 0xDEADBABE)]
-        pub fn raise_dispute(&mut self, name: Hash, cid: Hash) {
+        pub fn raise_dispute(&mut self, name: Hash, cid: Hash) -> Result<()> {
             let caller = self.env().caller();
-            if let Some(data) = self.get_subscription(name.clone(), caller) {
-                self.unchecked_raise_dispute(name, cid, data.clone());
+            if let Some(data) = self.get_subscription(name.clone(), caller)? {
+                self.unchecked_raise_dispute(name, cid, data.clone())
             } else {
                 ink_env::debug_println!("not subscribed");
-                panic!("not subscribed");
+                Err(Error::NotSubscribed)
             }
         }
 
-        fn unchecked_raise_dispute(&mut self, hash_name: Hash, cid: Hash, data: SubscriberData) {
-            let epoch = self.get_current_epoch();
-            let caller = self.env().caller();
+        fn unchecked_raise_dispute(
+            &mut self,
+            hash_name: Hash,
+            cid: Hash,
+            _data: SubscriberData,
+        ) -> Result<()> {
+            let epoch = self.current_epoch()?;
+            self.raise_dispute_at(hash_name, cid, self.env().caller(), epoch)
+        }
+
+        /// A cid identifies one dispute only, it cannot be raised again while
+        /// another dispute uses it or once it has votes or a verdict.
+        fn raise_dispute_at(
+            &mut self,
+            hash_name: Hash,
+            cid: Hash,
+            caller: AccountId,
+            epoch: u32,
+        ) -> Result<()> {
             let expire = self.get_dispute_expiration();
 
+            if let Some((_cid, on)) = self.get_cid(hash_name, caller) {
+                if epoch.saturating_sub(on) < expire {
+                    ink_env::debug_println!("dispute already in place");
+                    return Err(Error::DisputeAlreadyInPlace);
+                }
+            }
+            if self.veredict.contains_key(&cid)
+                || self.decided.contains_key(&cid)
+                || self.cid_exists_at(cid, epoch)
+            {
+                ink_env::debug_println!("cid in use");
+                return Err(Error::CidInUse);
+            }
             self.dispute
                 .entry(hash_name.clone())
                 .and_modify(|old_value_map| {
-                    old_value_map
-                        .entry(caller.clone())
-                        .and_modify(|ov| {
-                            let (ref _cid, ref on) = ov;
-                            if epoch - on > expire {
-                                *ov = (cid.clone(), epoch.clone());
-                            } else {
-                                panic!("dispute already in place");
-                            }
-                        })
-                        .or_insert((cid.clone(), epoch.clone()));
+                    old_value_map.insert(caller.clone(), (cid.clone(), epoch.clone()));
                 })
                 .or_insert({
                     let mut map = BTreeMap::new();
@@ -182,33 +284,34 @@ mod dispute {
                     cid,
                 },
             );
+            Ok(())
         }
 
         #[ink(message, selector = 0xCAFEBABE)]
-        pub fn withdraw_dispute(&mut self, name: Hash) {
+        pub fn withdraw_dispute(&mut self, name: Hash) -> Result<()> {
             let caller = self.env().caller();
-            let checked = if let Some(data) = self.get_subscription(name.clone(), caller) {
+            let checked = if let Some(_data) = self.get_subscription(name.clone(), caller)? {
                 if let Some(disputes) = self.dispute.get(&name) {
                     disputes.get(&caller).cloned()
                 } else {
                     ink_env::debug_println!("name not found");
-                    panic!("name not found")
+                    return Err(Error::NameNotFound);
                 }
             } else {
                 ink_env::debug_println!("not subscribed");
-                panic!("not subscribed");
+                return Err(Error::NotSubscribed);
             };
 
             if let Some(data) = checked {
                 let (ref cid, ref _on) = data;
-                self.unchecked_withdraw_dispute(name, cid.clone());
+                self.unchecked_withdraw_dispute(name, cid.clone())
             } else {
                 ink_env::debug_println!("caller not found");
-                panic!("caller not found");
+                Err(Error::DisputeNotFound)
             }
         }
 
-        fn unchecked_withdraw_dispute(&mut self, hash_name: Hash, cid: Hash) {
+        fn unchecked_withdraw_dispute(&mut self, hash_name: Hash, cid: Hash) -> Result<()> {
             let caller = self.env().caller();
             if let Some(a) = self.dispute.get_mut(&hash_name) {
                 if let Some(_) = a.remove(&caller) {
@@ -221,18 +324,53 @@ mod dispute {
                             cid,
                         },
                     );
+                    Ok(())
                 } else {
                     ink_env::debug_println!("failed to remove caller");
-                    panic!("failed to remove caller");
+                    Err(Error::DisputeNotFound)
                 }
             } else {
                 ink_env::debug_println!("name not found");
-                panic!("name not found");
+                Err(Error::NameNotFound)
+            }
+        }
+
+        /// Majority of the judge votes on the dispute `subscriber` raised on
+        /// `hash_name` with `cid`, `true` when the dispute is upheld. `None`
+        /// without votes, on a tie or when the cid is not the one of that
+        /// dispute. Called by `Subscription` through
+        /// `subscription::GET_VERDICT` to settle challenged receipts, the
+        /// verdict outlives the votes once the dispute expired.
+        #[ink(message, selector = 0xDEC1DE00)]
+        pub fn get_verdict(
+            &self,
+            hash_name: Hash,
+            subscriber: AccountId,
+            cid: Hash,
+        ) -> Option<bool> {
+            match self.get_cid(hash_name, subscriber) {
+                Some((raised, _on)) if raised == cid => self.majority(cid),
+                _ => self
+                    .decided
+                    .get(&cid)
+                    .filter(|(name, from, _)| *name == hash_name && *from == subscriber)
+                    .map(|(_, _, upheld)| *upheld),
+            }
+        }
+
+        fn majority(&self, cid: Hash) -> Option<bool> {
+            let votes = self.veredict.get(&cid)?;
+            let upheld = votes.values().filter(|vote| **vote).count();
+            let rejected = votes.len() - upheld;
+            if upheld == rejected {
+                None
+            } else {
+                Some(upheld > rejected)
             }
         }
 
         #[ink(message)]
-        pub fn submit_vote(&mut self, cid: Hash, vote: bool) {
+        pub fn submit_vote(&mut self, cid: Hash, vote: bool) -> Result<()> {
             let is_judge = self.is_judge();
             if is_judge {
                 let cid_exists = self.cid_exists(cid.clone());
@@ -253,13 +391,14 @@ mod dispute {
                             let _ = map.insert(caller, vote);
                             map
                         });
+                    Ok(())
                 } else {
                     ink_env::debug_println!("invalid cid");
-                    panic!("invalid cid");
+                    Err(Error::InvalidCid)
                 }
             } else {
                 ink_env::debug_println!("not a judge");
-                panic!("not a judge");
+                Err(Error::NotJudge)
             }
         }
     }
@@ -281,5 +420,116 @@ mod dispute {
             // let dispute = Dispute::default();
             assert!(true);
         }
+
+        /// The verdict follows the majority of votes.
+        #[ink::test]
+        fn verdict_is_majority() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Off-chain environment should have been initialized already");
+            let mut dispute = Dispute::new(
+                accounts.alice,
+                ink_env::call::FromAccountId::from_account_id(accounts.django),
+            );
+            let name = Hash::from([2; 32]);
+            let cid = Hash::from([1; 32]);
+            let mut map = BTreeMap::new();
+            map.insert(accounts.bob, (cid, 0));
+            dispute.dispute.insert(name, map);
+            assert_eq!(dispute.get_verdict(name, accounts.bob, cid), None);
+
+            let mut votes = BTreeMap::new();
+            votes.insert(accounts.alice, true);
+            votes.insert(accounts.bob, false);
+            dispute.veredict.insert(cid, votes.clone());
+            assert_eq!(dispute.get_verdict(name, accounts.bob, cid), None);
+
+            votes.insert(accounts.eve, true);
+            dispute.veredict.insert(cid, votes.clone());
+            assert_eq!(dispute.get_verdict(name, accounts.bob, cid), Some(true));
+
+            votes.insert(accounts.eve, false);
+            dispute.veredict.insert(cid, votes);
+            assert_eq!(dispute.get_verdict(name, accounts.bob, cid), Some(false));
+        }
+
+        /// A dispute stays active for `get_dispute_expiration` epochs.
+        #[ink::test]
+        fn cid_exists_at_epoch_boundaries() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Off-chain environment should have been initialized already");
+            let mut dispute = Dispute::new(
+                accounts.alice,
+                ink_env::call::FromAccountId::from_account_id(accounts.django),
+            );
+            let cid = Hash::from([1; 32]);
+            let mut map = BTreeMap::new();
+            map.insert(accounts.bob, (cid, 5));
+            dispute.dispute.insert(Hash::from([2; 32]), map);
+
+            assert!(dispute.cid_exists_at(cid, 0));
+            assert!(dispute.cid_exists_at(cid, 14));
+            assert!(!dispute.cid_exists_at(cid, 15));
+            assert!(!dispute.cid_exists_at(Hash::from([3; 32]), 5));
+
+            dispute.prune_expired_at(14);
+            assert!(dispute.get_cid(Hash::from([2; 32]), accounts.bob).is_some());
+            let mut votes = BTreeMap::new();
+            votes.insert(accounts.alice, true);
+            dispute.veredict.insert(cid, votes);
+            dispute.prune_expired_at(15);
+            assert!(dispute.get_cid(Hash::from([2; 32]), accounts.bob).is_none());
+            assert!(dispute.dispute.is_empty());
+            // votes are dropped but the verdict is kept
+            assert!(dispute.veredict.is_empty());
+            assert_eq!(
+                dispute.get_verdict(Hash::from([2; 32]), accounts.bob, cid),
+                Some(true)
+            );
+        }
+
+        /// A cid with a dispute or a verdict cannot be raised again, so its
+        /// verdict only applies to the subscriber who raised it.
+        #[ink::test]
+        fn reused_cid_is_rejected() {
+            let accounts = ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Off-chain environment should have been initialized already");
+            let mut dispute = Dispute::new(
+                accounts.alice,
+                ink_env::call::FromAccountId::from_account_id(accounts.django),
+            );
+            let name = Hash::from([2; 32]);
+            let cid = Hash::from([1; 32]);
+            assert_eq!(dispute.raise_dispute_at(name, cid, accounts.bob, 0), Ok(()));
+            assert_eq!(
+                dispute.raise_dispute_at(name, cid, accounts.eve, 0),
+                Err(Error::CidInUse)
+            );
+            assert_eq!(
+                dispute.raise_dispute_at(Hash::from([3; 32]), cid, accounts.eve, 0),
+                Err(Error::CidInUse)
+            );
+
+            let mut votes = BTreeMap::new();
+            votes.insert(accounts.alice, true);
+            dispute.veredict.insert(cid, votes);
+            assert_eq!(dispute.get_verdict(name, accounts.bob, cid), Some(true));
+            assert_eq!(dispute.get_verdict(name, accounts.eve, cid), None);
+
+            dispute.prune_expired_at(10);
+            assert_eq!(
+                dispute.raise_dispute_at(name, cid, accounts.eve, 11),
+                Err(Error::CidInUse)
+            );
+            assert_eq!(
+                dispute.raise_dispute_at(name, cid, accounts.bob, 11),
+                Err(Error::CidInUse)
+            );
+            assert_eq!(dispute.get_verdict(name, accounts.bob, cid), Some(true));
+            assert_eq!(dispute.get_verdict(name, accounts.eve, cid), None);
+            assert_eq!(
+                dispute.raise_dispute_at(name, Hash::from([4; 32]), accounts.bob, 11),
+                Ok(())
+            );
+        }
     }
 }
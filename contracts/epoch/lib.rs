@@ -2,71 +2,372 @@
 
 use ink_lang as ink;
 
-pub use self::epoch::Epoch;
+pub use self::epoch::{Epoch, Error, Result, ON_EPOCH_STARTED};
 
 #[ink::contract]
 mod epoch {
+    use ink_env::call::{build_call, ExecutionInput, Selector};
+    use ink_prelude::vec::Vec;
+    use ink_storage::collections::Vec as StorageVec;
+
+    /// Selector of the `on_epoch_started(epoch: u32)` message hooks implement.
+    pub const ON_EPOCH_STARTED: [u8; 4] = [0xE9, 0x0C, 0xBA, 0xBE];
+
+    /// Errors that can occur upon calling this contract.
+    #[cfg_attr(feature = "std", derive(::scale_info::TypeInfo))]
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, scale::Encode, scale::Decode)]
+    pub enum Error {
+        /// Caller is not the owner of the epoch parameters.
+        NotOwner,
+        /// Offset can only change before the first epoch started.
+        EpochStarted,
+        /// Period must be at least one block.
+        ZeroPeriod,
+        /// Epoch arithmetic overflowed.
+        Overflow,
+        /// Epoch schedule did not start yet.
+        BeforeGenesis,
+        /// Current epoch was already advanced.
+        AlreadyAdvanced,
+        /// Contract is already registered as a hook.
+        HookExists,
+        /// Contract is not registered as a hook.
+        HookNotFound,
+    }
+
+    /// Type alias for the contract's result type.
+    pub type Result<T> = core::result::Result<T, Error>;
 
     /// Defines the storage of your contract.
     /// Add new fields to the below struct in order
     /// to add new static storage fields to your contract.
     #[ink(storage)]
     pub struct Epoch {
-        offset: BlockNumber,
-        period: BlockNumber,
+        /// Append-only `(start_block, start_epoch, period)` schedule, only the
+        /// last segment may be changed and only until it starts.
+        segments: StorageVec<(BlockNumber, u32, BlockNumber)>,
+        owner: AccountId,
+        /// Last epoch announced by `advance_epoch`.
+        advanced: Option<u32>,
+        /// Contracts called with `ON_EPOCH_STARTED` on every advance.
+        hooks: Vec<AccountId>,
+        /// Paid from the contract balance to the caller of `advance_epoch`.
+        bounty: Balance,
+    }
+
+    /// Emitted whenever `advance_epoch` announces a new epoch.
+    #[ink(event)]
+    pub struct EpochStarted {
+        #[ink(topic)]
+        epoch: u32,
+        start_block: BlockNumber,
+    }
+
+    /// Emitted whenever the owner adds or removes a hook.
+    #[ink(event)]
+    pub struct HookChanged {
+        #[ink(topic)]
+        hook: AccountId,
+        registered: bool,
+    }
+
+    /// Emitted whenever the owner changes the offset or the period, the new
+    /// values apply from `start_epoch`.
+    #[ink(event)]
+    pub struct EpochParametersChanged {
+        #[ink(topic)]
+        by: AccountId,
+        start_epoch: u32,
+        old_offset: BlockNumber,
+        new_offset: BlockNumber,
+        old_period: BlockNumber,
+        new_period: BlockNumber,
+    }
+
+    /// Emitted whenever the ownership is handed to another account.
+    #[ink(event)]
+    pub struct OwnershipTransferred {
+        #[ink(topic)]
+        from: AccountId,
+        #[ink(topic)]
+        to: AccountId,
     }
 
     impl Epoch {
-        /// Constructor that initializes the `bool` value to the given `init_value`.
+        /// Initializes offset and period, the caller becomes the owner
+        /// allowed to change them.
         #[ink(constructor)]
         pub fn new(offset: BlockNumber, period: BlockNumber) -> Self {
-            Self { offset, period }
+            assert!(period > 0, "period must be at least one block");
+            let mut segments = StorageVec::new();
+            segments.push((offset, 0, period));
+            Self {
+                segments,
+                owner: Self::env().caller(),
+                advanced: None,
+                hooks: Vec::new(),
+                bounty: 0,
+            }
+        }
+
+        /// set the offset from genesis where period begin, owner only and
+        /// only before the first epoch started.
+        #[ink(message)]
+        pub fn set_offset(&mut self, offset: BlockNumber) -> Result<()> {
+            self.ensure_owner()?;
+            let (start, _e, period) = self.first_segment();
+            if self.segments.len() > 1 || self.env().block_number() >= start {
+                return Err(Error::EpochStarted);
+            }
+            if let Some(first) = self.segments.first_mut() {
+                first.0 = offset;
+            }
+            self.emit_changed(0, start, period);
+            Ok(())
+        }
+
+        /// set period for each epoch, owner only. The new period applies from
+        /// the next epoch boundary, past epochs keep their numbers.
+        #[ink(message)]
+        pub fn set_period(&mut self, period: BlockNumber) -> Result<()> {
+            self.ensure_owner()?;
+            if period == 0 {
+                return Err(Error::ZeroPeriod);
+            }
+            let now = self.env().block_number();
+            let offset = self.get_offset();
+            let (start, start_epoch, old_period) = self.last_segment();
+            if start > now {
+                // Not started yet, adjust the pending segment.
+                if let Some(last) = self.segments.last_mut() {
+                    last.2 = period;
+                }
+                self.emit_changed(start_epoch, offset, old_period);
+                return Ok(());
+            }
+            let next = self
+                .epoch_at_block(now)
+                .and_then(|epoch| epoch.checked_add(1))
+                .ok_or(Error::Overflow)?;
+            let next_start = self.epoch_start_block(next).ok_or(Error::Overflow)?;
+            self.segments.push((next_start, next, period));
+            self.emit_changed(next, offset, old_period);
+            Ok(())
+        }
+
+        fn ensure_owner(&self) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::NotOwner);
+            }
+            Ok(())
+        }
+
+        fn emit_changed(&self, start_epoch: u32, old_offset: BlockNumber, old_period: BlockNumber) {
+            let (_s, _e, new_period) = self.last_segment();
+            self.env().emit_event(EpochParametersChanged {
+                by: self.env().caller(),
+                start_epoch,
+                old_offset,
+                new_offset: self.get_offset(),
+                old_period,
+                new_period,
+            });
+        }
+
+        fn first_segment(&self) -> (BlockNumber, u32, BlockNumber) {
+            *self
+                .segments
+                .first()
+                .expect("schedule always has a segment")
         }
 
-        /// set the offset from genesis where period begin.
+        fn last_segment(&self) -> (BlockNumber, u32, BlockNumber) {
+            *self.segments.last().expect("schedule always has a segment")
+        }
+
+        /// Hands the right to change the epoch parameters to `new_owner`.
+        #[ink(message)]
+        pub fn transfer_ownership(&mut self, new_owner: AccountId) -> Result<()> {
+            self.ensure_owner()?;
+            let caller = self.env().caller();
+            self.owner = new_owner;
+            self.env().emit_event(OwnershipTransferred {
+                from: caller,
+                to: new_owner,
+            });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_owner(&self) -> AccountId {
+            self.owner
+        }
+
+        /// Announces the current epoch once, callable by anyone. Emits
+        /// `EpochStarted`, calls every hook and pays `bounty` to the caller
+        /// while the pool has enough balance, a failed payment is skipped.
+        /// Skipped epochs are not replayed.
+        #[ink(message)]
+        pub fn advance_epoch(&mut self) -> Result<u32> {
+            let epoch = self.get_current_epoch().ok_or(Error::BeforeGenesis)?;
+            if let Some(last) = self.advanced {
+                if last >= epoch {
+                    return Err(Error::AlreadyAdvanced);
+                }
+            }
+            let start_block = self.epoch_start_block(epoch).ok_or(Error::Overflow)?;
+            let bounty = self.bounty;
+            if bounty > 0 && self.env().balance() >= bounty {
+                // A failing bounty transfer must not block the epoch from
+                // advancing either, the caller just goes without it.
+                let _ = self.env().transfer(self.env().caller(), bounty);
+            }
+            self.advanced = Some(epoch);
+            self.env().emit_event(EpochStarted { epoch, start_block });
+            for hook in self.hooks.iter() {
+                // A failing hook must not block the epoch from advancing.
+                let _ = build_call::<ink_env::DefaultEnvironment>()
+                    .callee(*hook)
+                    .exec_input(
+                        ExecutionInput::new(Selector::new(ON_EPOCH_STARTED)).push_arg(epoch),
+                    )
+                    .returns::<()>()
+                    .fire();
+            }
+            Ok(epoch)
+        }
+
+        /// Returns the last epoch announced by `advance_epoch`.
+        #[ink(message)]
+        pub fn get_advanced_epoch(&self) -> Option<u32> {
+            self.advanced
+        }
+
+        /// Registers a contract to be called on every `advance_epoch`.
+        #[ink(message)]
+        pub fn add_hook(&mut self, hook: AccountId) -> Result<()> {
+            self.ensure_owner()?;
+            if self.hooks.contains(&hook) {
+                return Err(Error::HookExists);
+            }
+            self.hooks.push(hook);
+            self.env().emit_event(HookChanged {
+                hook,
+                registered: true,
+            });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn remove_hook(&mut self, hook: AccountId) -> Result<()> {
+            self.ensure_owner()?;
+            let index = self
+                .hooks
+                .iter()
+                .position(|h| *h == hook)
+                .ok_or(Error::HookNotFound)?;
+            self.hooks.swap_remove(index);
+            self.env().emit_event(HookChanged {
+                hook,
+                registered: false,
+            });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_hooks(&self) -> Vec<AccountId> {
+            self.hooks.clone()
+        }
+
+        /// Sets the amount paid to each caller of `advance_epoch`.
         #[ink(message)]
-        pub fn set_offset(&mut self, offset: BlockNumber) {
-            self.offset = offset;
+        pub fn set_bounty(&mut self, bounty: Balance) -> Result<()> {
+            self.ensure_owner()?;
+            self.bounty = bounty;
+            Ok(())
         }
 
-        /// set period for each epoch.
         #[ink(message)]
-        pub fn set_period(&mut self, period: BlockNumber) {
-            self.period = period;
+        pub fn get_bounty(&self) -> Balance {
+            self.bounty
+        }
+
+        /// Adds the transferred balance to the bounty pool.
+        #[ink(message, payable)]
+        pub fn fund_bounty(&mut self) {
+            ink_env::debug_println!("bounty pool funded: {}", self.env().transferred_balance());
         }
 
         /// Simply returns the current value of offset.
         #[ink(message)]
         pub fn get_offset(&self) -> BlockNumber {
-            self.offset
+            self.first_segment().0
         }
 
-        /// Simply returns the current value of offset.
+        /// Returns the period of the epoch running at the current block.
         #[ink(message)]
         pub fn get_period_length(&self) -> BlockNumber {
-            self.period
+            self.segment_at_block(self.env().block_number())
+                .unwrap_or_else(|| self.first_segment())
+                .2
+        }
+
+        /// Returns the whole schedule as `(start_block, start_epoch, period)`.
+        #[ink(message)]
+        pub fn get_schedule(&self) -> Vec<(BlockNumber, u32, BlockNumber)> {
+            self.segments.iter().cloned().collect()
+        }
+
+        fn segment_at_block(&self, block: BlockNumber) -> Option<(BlockNumber, u32, BlockNumber)> {
+            self.segments
+                .iter()
+                .rev()
+                .find(|(start, _e, _p)| *start <= block)
+                .cloned()
+        }
+
+        /// Returns the epoch running at `block`, `None` before the offset.
+        #[ink(message)]
+        pub fn epoch_at_block(&self, block: BlockNumber) -> Option<u32> {
+            let (start, start_epoch, period) = self.segment_at_block(block)?;
+            let elapsed = block.checked_sub(start)?.checked_div(period)?;
+            start_epoch.checked_add(elapsed)
+        }
+
+        /// Returns the first block of `epoch`, `None` on overflow.
+        #[ink(message)]
+        pub fn epoch_start_block(&self, epoch: u32) -> Option<BlockNumber> {
+            let (start, start_epoch, period) = self
+                .segments
+                .iter()
+                .rev()
+                .find(|(_s, e, _p)| *e <= epoch)
+                .cloned()?;
+            let elapsed = epoch.checked_sub(start_epoch)?.checked_mul(period)?;
+            start.checked_add(elapsed)
         }
 
-        /// Simply returns the current value of epoch.
+        /// Simply returns the current value of epoch, `None` before the offset.
         #[ink(message)]
-        pub fn get_current_epoch(&self) -> u32 {
-            let off = self.env().block_number() - self.offset;
-            off / self.period
+        pub fn get_current_epoch(&self) -> Option<u32> {
+            self.epoch_at_block(self.env().block_number())
         }
 
-        /// Simply returns the value of epoch since param.
+        /// Simply returns the value of epoch since param, `None` when `since`
+        /// is before the offset or in a later epoch.
         #[ink(message)]
-        pub fn get_current_epoch_since(&self, since: BlockNumber) -> u32 {
-            let s = (since - self.offset) / self.period;
-            let off = (self.env().block_number() - self.offset) / self.period;
-            off - s
+        pub fn get_current_epoch_since(&self, since: BlockNumber) -> Option<u32> {
+            self.get_current_epoch()?
+                .checked_sub(self.epoch_at_block(since)?)
         }
 
-        /// Simply returns the current value of block inside epoch.
+        /// Simply returns the current value of block inside epoch, `None`
+        /// before the offset.
         #[ink(message)]
-        pub fn get_current_block(&self) -> u32 {
-            let off = self.env().block_number() - self.offset;
-            off % self.period
+        pub fn get_current_block(&self) -> Option<u32> {
+            let now = self.env().block_number();
+            let start = self.epoch_start_block(self.epoch_at_block(now)?)?;
+            now.checked_sub(start)
         }
     }
 
@@ -81,20 +382,171 @@ mod epoch {
         /// Imports `ink_lang` so we can use `#[ink::test]`.
         use ink_lang as ink;
 
-        /// We test if the default constructor does its job.
+        /// We test if the constructor does its job.
         #[ink::test]
         fn default_works() {
-            let epoch = Epoch::default();
-            assert_eq!(epoch.get(), false);
+            let accounts = default_accounts();
+            let epoch = Epoch::new(0, 10);
+            assert_eq!(epoch.get_offset(), 0);
+            assert_eq!(epoch.get_period_length(), 10);
+            assert_eq!(epoch.get_owner(), accounts.alice);
+        }
+
+        /// Only the owner changes the parameters and can hand them over.
+        #[ink::test]
+        fn parameters_are_owner_only() {
+            let accounts = default_accounts();
+            let mut epoch = Epoch::new(10, 10);
+
+            set_sender(accounts.bob);
+            assert_eq!(epoch.set_period(1), Err(Error::NotOwner));
+            assert_eq!(epoch.set_offset(1), Err(Error::NotOwner));
+            assert_eq!(epoch.transfer_ownership(accounts.bob), Err(Error::NotOwner));
+
+            set_sender(accounts.alice);
+            assert_eq!(epoch.set_period(20), Ok(()));
+            assert_eq!(epoch.set_offset(5), Ok(()));
+            assert_eq!(epoch.get_period_length(), 20);
+            assert_eq!(epoch.get_offset(), 5);
+            assert_eq!(ink_env::test::recorded_events().count(), 2);
+
+            assert_eq!(epoch.transfer_ownership(accounts.bob), Ok(()));
+            assert_eq!(epoch.set_period(30), Err(Error::NotOwner));
+            set_sender(accounts.bob);
+            assert_eq!(epoch.set_period(30), Ok(()));
+            assert_eq!(epoch.get_period_length(), 30);
         }
 
-        /// We test a simple use case of our contract.
+        /// A new period starts at the next boundary and keeps past epochs.
         #[ink::test]
-        fn it_works() {
-            let mut epoch = Epoch::new(false);
-            assert_eq!(epoch.get(), false);
-            epoch.flip();
-            assert_eq!(epoch.get(), true);
+        fn period_change_keeps_history() {
+            let mut epoch = Epoch::new(0, 10);
+            advance_blocks(15);
+            assert_eq!(epoch.get_current_epoch(), Some(1));
+
+            assert_eq!(epoch.set_period(5), Ok(()));
+            assert_eq!(epoch.get_current_epoch(), Some(1));
+            assert_eq!(epoch.get_period_length(), 10);
+            assert_eq!(epoch.set_offset(3), Err(Error::EpochStarted));
+            assert_eq!(epoch.epoch_at_block(12), Some(1));
+            assert_eq!(epoch.epoch_at_block(19), Some(1));
+            assert_eq!(epoch.epoch_at_block(20), Some(2));
+            assert_eq!(epoch.epoch_at_block(25), Some(3));
+            assert_eq!(epoch.epoch_start_block(1), Some(10));
+            assert_eq!(epoch.epoch_start_block(3), Some(25));
+
+            assert_eq!(epoch.set_period(4), Ok(()));
+            assert_eq!(epoch.get_schedule(), vec![(0, 0, 10), (20, 2, 4)]);
+            assert_eq!(epoch.epoch_at_block(24), Some(3));
+
+            advance_blocks(10);
+            assert_eq!(epoch.get_current_epoch(), Some(3));
+            assert_eq!(epoch.get_current_block(), Some(1));
+            assert_eq!(epoch.get_current_epoch_since(12), Some(2));
+            assert_eq!(epoch.set_period(10), Ok(()));
+            assert_eq!(
+                epoch.get_schedule(),
+                vec![(0, 0, 10), (20, 2, 4), (28, 4, 10)]
+            );
+            assert_eq!(epoch.epoch_at_block(24), Some(3));
+        }
+
+        /// Blocks before the offset have no epoch and nothing wraps around.
+        #[ink::test]
+        fn before_offset_has_no_epoch() {
+            let mut epoch = Epoch::new(10, 10);
+            assert_eq!(epoch.get_current_epoch(), None);
+            assert_eq!(epoch.get_current_block(), None);
+            assert_eq!(epoch.get_current_epoch_since(0), None);
+            assert_eq!(epoch.epoch_at_block(9), None);
+            assert_eq!(epoch.epoch_at_block(10), Some(0));
+            assert_eq!(epoch.epoch_at_block(19), Some(0));
+            assert_eq!(epoch.epoch_at_block(20), Some(1));
+            assert_eq!(epoch.epoch_start_block(0), Some(10));
+            assert_eq!(epoch.set_period(0), Err(Error::ZeroPeriod));
+
+            advance_blocks(10);
+            assert_eq!(epoch.get_current_epoch(), Some(0));
+            assert_eq!(epoch.get_current_block(), Some(0));
+            assert_eq!(epoch.get_current_epoch_since(9), None);
+            assert_eq!(epoch.get_current_epoch_since(10), Some(0));
+            assert_eq!(epoch.get_current_epoch_since(11), Some(0));
+        }
+
+        /// Each epoch is announced once and pays the bounty to the caller.
+        #[ink::test]
+        fn advance_epoch_pays_bounty_once() {
+            let accounts = default_accounts();
+            let mut epoch = Epoch::new(5, 10);
+            assert_eq!(epoch.advance_epoch(), Err(Error::BeforeGenesis));
+
+            assert_eq!(epoch.set_bounty(10), Ok(()));
+            set_balance(contract_id(), 100);
+            set_balance(accounts.bob, 0);
+            advance_blocks(5);
+            set_sender(accounts.bob);
+            assert_eq!(epoch.set_bounty(1), Err(Error::NotOwner));
+            assert_eq!(epoch.advance_epoch(), Ok(0));
+            assert_eq!(epoch.get_advanced_epoch(), Some(0));
+            assert_eq!(epoch.advance_epoch(), Err(Error::AlreadyAdvanced));
+            assert_eq!(get_balance(accounts.bob), 10);
+
+            advance_blocks(25);
+            assert_eq!(epoch.advance_epoch(), Ok(2));
+            assert_eq!(get_balance(accounts.bob), 20);
+        }
+
+        /// Only the owner manages hooks.
+        #[ink::test]
+        fn hooks_are_owner_only() {
+            let accounts = default_accounts();
+            let mut epoch = Epoch::new(0, 10);
+            set_sender(accounts.bob);
+            assert_eq!(epoch.add_hook(accounts.django), Err(Error::NotOwner));
+
+            set_sender(accounts.alice);
+            assert_eq!(epoch.add_hook(accounts.django), Ok(()));
+            assert_eq!(epoch.add_hook(accounts.django), Err(Error::HookExists));
+            assert_eq!(epoch.get_hooks(), vec![accounts.django]);
+            assert_eq!(epoch.remove_hook(accounts.django), Ok(()));
+            assert_eq!(epoch.remove_hook(accounts.django), Err(Error::HookNotFound));
+        }
+
+        fn contract_id() -> AccountId {
+            ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into())
+        }
+
+        fn set_balance(account_id: AccountId, balance: Balance) {
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(account_id, balance)
+                .expect("Cannot set account balance");
+        }
+
+        fn get_balance(account_id: AccountId) -> Balance {
+            ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(account_id)
+                .expect("Cannot get account balance")
+        }
+
+        fn advance_blocks(count: u32) {
+            for _ in 0..count {
+                ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            }
+        }
+
+        fn set_sender(sender: AccountId) {
+            let callee =
+                ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                sender,
+                callee,
+                1000000,
+                1000000,
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])), // dummy
+            );
+        }
+
+        fn default_accounts() -> ink_env::test::DefaultAccounts<ink_env::DefaultEnvironment> {
+            ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Off-chain environment should have been initialized already")
         }
     }
 }
@@ -2,15 +2,29 @@
 
 use ink_lang as ink;
 
-pub use self::indexer_meta::IndexerMeta;
+pub use self::indexer_meta::{Error, IndexerMeta, Result};
 
 #[ink::contract]
 mod indexer_meta {
+    use directory::{resolve, Directory, REGISTRY};
     use ink_lang::EmitEvent;
     use ink_prelude::collections::BTreeMap;
     use ink_prelude::string::String;
     use ink_storage::collections::HashMap as StorageHashMap;
-    use registry_proxy::RegistryProxy;
+    use registry::Registry;
+
+    /// Errors that can occur upon calling this contract.
+    #[cfg_attr(feature = "std", derive(::scale_info::TypeInfo))]
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, scale::Encode, scale::Decode)]
+    pub enum Error {
+        /// Caller is not the owner of the name.
+        NotOwner,
+        /// Component is not set in the directory.
+        ComponentNotFound,
+    }
+
+    /// Type alias for the contract's result type.
+    pub type Result<T> = core::result::Result<T, Error>;
 
     /// Defines the storage of your contract.
     /// Add new fields to the below struct in order
@@ -19,7 +33,7 @@ mod indexer_meta {
     pub struct IndexerMeta {
         link: StorageHashMap<Hash, String>,
         capabilities: StorageHashMap<Hash, BTreeMap<Hash, String>>,
-        registry: RegistryProxy,
+        directory: Directory,
     }
 
     /// Emitted whenever a new Link is being registered.
@@ -44,28 +58,33 @@ mod indexer_meta {
 
     impl IndexerMeta {
         #[ink(constructor)]
-        pub fn new(init_value: RegistryProxy) -> Self {
+        pub fn new(directory: Directory) -> Self {
             Self {
-                registry: init_value,
+                directory,
                 link: Default::default(),
                 capabilities: Default::default(),
             }
         }
 
+        fn registry(&self) -> Result<Registry> {
+            resolve(&self.directory, REGISTRY).ok_or(Error::ComponentNotFound)
+        }
+
         #[ink(message, selector = 0xCAFEDEAD)]
-        pub fn set_link(&mut self, name: Hash, link: String) {
-            if self.is_owner(name) {
+        pub fn set_link(&mut self, name: Hash, link: String) -> Result<()> {
+            if self.is_owner(name)? {
                 self.set_link_unchecked(name, link);
+                Ok(())
             } else {
                 ink_env::debug_println!("not the owner");
-                panic!("not the owner");
+                Err(Error::NotOwner)
             }
         }
 
-        fn is_owner(&self, hash_name: Hash) -> bool {
-            self.registry
-                .get()
-                .is_owner_from(hash_name.clone(), self.env().caller().clone())
+        fn is_owner(&self, hash_name: Hash) -> Result<bool> {
+            Ok(self
+                .registry()?
+                .is_owner_from(hash_name.clone(), self.env().caller().clone()))
         }
 
         fn set_link_unchecked(&mut self, name_hash: Hash, link: String) {
@@ -89,12 +108,13 @@ mod indexer_meta {
         }
 
         #[ink(message, selector = 0xCAFE)]
-        pub fn set_capability(&mut self, name: Hash, property: Hash, value: String) {
-            if self.is_owner(name) {
+        pub fn set_capability(&mut self, name: Hash, property: Hash, value: String) -> Result<()> {
+            if self.is_owner(name)? {
                 self.set_capability_unchecked(name, property, value);
+                Ok(())
             } else {
                 ink_env::debug_println!("not the owner");
-                panic!("not the owner");
+                Err(Error::NotOwner)
             }
         }
 
@@ -0,0 +1,160 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use ink_lang as ink;
+
+pub use self::proxy::{Error, Proxy, Result};
+
+#[ink::contract]
+mod proxy {
+
+    /// Errors that can occur upon calling this contract.
+    #[cfg_attr(feature = "std", derive(::scale_info::TypeInfo))]
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, scale::Encode, scale::Decode)]
+    pub enum Error {
+        /// Caller is not the proxy admin.
+        NotAdmin,
+    }
+
+    /// Type alias for the contract's result type.
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    /// Stable address for one DAO component, clients resolve the current
+    /// deployment through `get` and the admin repoints it with `set`. It
+    /// does not forward calls, so state stays with each deployment.
+    #[ink(storage)]
+    pub struct Proxy {
+        target: AccountId,
+        admin: AccountId,
+        version: u32,
+    }
+
+    /// Emitted whenever the admin points the proxy to a new contract.
+    #[ink(event)]
+    pub struct Upgraded {
+        #[ink(topic)]
+        by: AccountId,
+        old: AccountId,
+        #[ink(topic)]
+        new: AccountId,
+        version: u32,
+    }
+
+    impl Proxy {
+        /// Points to `target`, the caller becomes the admin.
+        #[ink(constructor)]
+        pub fn new(target: AccountId) -> Self {
+            Self {
+                target,
+                admin: Self::env().caller(),
+                version: 1,
+            }
+        }
+
+        /// Returns the address of the current component.
+        #[ink(message)]
+        pub fn get(&self) -> AccountId {
+            self.target
+        }
+
+        /// Points the proxy to `target` and bumps `version`.
+        #[ink(message)]
+        pub fn set(&mut self, target: AccountId) -> Result<()> {
+            let caller = self.env().caller();
+            if caller != self.admin {
+                return Err(Error::NotAdmin);
+            }
+            let old = self.target;
+            self.target = target;
+            self.version = self.version.saturating_add(1);
+            self.env().emit_event(Upgraded {
+                by: caller,
+                old,
+                new: target,
+                version: self.version,
+            });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn admin(&self) -> AccountId {
+            self.admin
+        }
+
+        #[ink(message)]
+        pub fn set_admin(&mut self, new_admin: AccountId) -> Result<()> {
+            if self.env().caller() != self.admin {
+                return Err(Error::NotAdmin);
+            }
+            self.admin = new_admin;
+            Ok(())
+        }
+
+        /// Returns how many targets the proxy pointed to, starting at 1.
+        #[ink(message)]
+        pub fn version(&self) -> u32 {
+            self.version
+        }
+    }
+
+    /// Unit tests in Rust are normally defined within such a `#[cfg(test)]`
+    /// module and test functions are marked with a `#[test]` attribute.
+    /// The below code is technically just normal Rust code.
+    #[cfg(test)]
+    mod tests {
+        /// Imports all the definitions from the outer scope so we can use them here.
+        use super::*;
+
+        /// Imports `ink_lang` so we can use `#[ink::test]`.
+        use ink_lang as ink;
+
+        /// We test if the constructor does its job.
+        #[ink::test]
+        fn default_works() {
+            let accounts = default_accounts();
+            let proxy = Proxy::new(accounts.django);
+            assert_eq!(proxy.get(), accounts.django);
+            assert_eq!(proxy.admin(), accounts.alice);
+            assert_eq!(proxy.version(), 1);
+        }
+
+        /// Only the admin repoints the proxy, each change bumps the version.
+        #[ink::test]
+        fn set_is_admin_only() {
+            let accounts = default_accounts();
+            let mut proxy = Proxy::new(accounts.django);
+
+            set_sender(accounts.bob);
+            assert_eq!(proxy.set(accounts.eve), Err(Error::NotAdmin));
+            assert_eq!(proxy.set_admin(accounts.bob), Err(Error::NotAdmin));
+
+            set_sender(accounts.alice);
+            assert_eq!(proxy.set(accounts.eve), Ok(()));
+            assert_eq!(proxy.get(), accounts.eve);
+            assert_eq!(proxy.version(), 2);
+            assert_eq!(ink_env::test::recorded_events().count(), 1);
+
+            assert_eq!(proxy.set_admin(accounts.bob), Ok(()));
+            set_sender(accounts.bob);
+            assert_eq!(proxy.set(accounts.django), Ok(()));
+            assert_eq!(proxy.get(), accounts.django);
+            assert_eq!(proxy.version(), 3);
+        }
+
+        fn set_sender(sender: AccountId) {
+            let callee =
+                ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                sender,
+                callee,
+                1000000,
+                1000000,
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])), // dummy
+            );
+        }
+
+        fn default_accounts() -> ink_env::test::DefaultAccounts<ink_env::DefaultEnvironment> {
+            ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Off-chain environment should have been initialized already")
+        }
+    }
+}
@@ -2,18 +2,232 @@
 
 use ink_lang as ink;
 
-pub use self::registry::Registry;
+pub use self::registry::{
+    Auction, Error, NameError, NameStatus, NormalizedName, Registry, RegistryConfig, Result,
+};
 // , RegistryRef};
 
 #[ink::contract]
 mod registry {
     use ink_env::{self, hash::Blake2x256};
     // use ink_prelude::collections::BTreeMap;
-    use epoch_proxy::EpochProxy;
+    use directory::{resolve, Directory, EPOCH};
+    use epoch::Epoch;
     use ink_prelude::string::String;
     use ink_prelude::vec::Vec;
     use ink_storage::collections::HashMap as StorageHashMap;
     use scale::Encode;
+    use unicode_normalization::UnicodeNormalization;
+
+    /// Lifecycle of a name as seen from the current epoch.
+    #[cfg_attr(feature = "std", derive(::scale_info::TypeInfo))]
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, scale::Encode, scale::Decode)]
+    pub enum NameStatus {
+        /// Registered and within its duration.
+        Active,
+        /// Past its duration, only the previous owner can renew it.
+        Grace,
+        /// Past its grace period, anyone can register it again.
+        Expired,
+        /// Never registered or unregistered by its owner.
+        Never,
+    }
+
+    /// Economic and validation parameters of the registry, tunable by the admin.
+    #[cfg_attr(
+        feature = "std",
+        derive(::scale_info::TypeInfo, ::ink_storage::traits::StorageLayout,)
+    )]
+    #[derive(
+        Debug,
+        PartialEq,
+        Eq,
+        Clone,
+        scale::Encode,
+        scale::Decode,
+        ::ink_storage::traits::SpreadLayout,
+        ::ink_storage::traits::PackedLayout,
+    )]
+    pub struct RegistryConfig {
+        /// Rate per epoch charged to subscribers of an indexer.
+        pub indexer_rate: Balance,
+        /// Minimum payment for a commitment.
+        pub commit_fee: Balance,
+        /// Balance locked on registration and returned on unregister.
+        pub locked_deposit: Balance,
+        /// Number of blocks before a commitment can be used to register.
+        pub min_commit_age: BlockNumber,
+        /// Number of blocks a commitment stays valid.
+        pub max_commit_age: BlockNumber,
+        /// Maximum name size in bytes.
+        pub max_name_length: u32,
+        /// Minimum name size in bytes, after normalization.
+        pub min_name_length: u32,
+        /// Only accept ASCII names instead of NFC normalized Unicode.
+        pub ascii_only: bool,
+        /// Rent price per byte of name per epoch.
+        pub rent_price: Balance,
+        /// Epochs after expiry where only the owner can renew.
+        pub grace_period: u32,
+        /// Names shorter than this many bytes can only be won in an auction.
+        pub auction_below_length: u32,
+        /// Epochs after an auction starts where sealed bids are accepted.
+        pub bidding_epochs: u32,
+        /// Epochs after bidding where bids can be revealed.
+        pub reveal_epochs: u32,
+        /// Duration in epochs of a name won in an auction.
+        pub auction_duration: u32,
+    }
+
+    impl Default for RegistryConfig {
+        fn default() -> Self {
+            Self {
+                indexer_rate: 10,
+                commit_fee: 10,
+                locked_deposit: 10,
+                min_commit_age: 1,
+                max_commit_age: 100,
+                max_name_length: 256,
+                min_name_length: 1,
+                ascii_only: false,
+                rent_price: 1_000,
+                grace_period: 0,
+                auction_below_length: 0,
+                bidding_epochs: 2,
+                reveal_epochs: 2,
+                auction_duration: 100,
+            }
+        }
+    }
+
+    /// Sealed-bid auction of a name, the highest revealed bid wins and pays
+    /// the second highest bid.
+    #[cfg_attr(
+        feature = "std",
+        derive(::scale_info::TypeInfo, ::ink_storage::traits::StorageLayout,)
+    )]
+    #[derive(
+        Debug,
+        PartialEq,
+        Eq,
+        Clone,
+        scale::Encode,
+        scale::Decode,
+        ::ink_storage::traits::SpreadLayout,
+        ::ink_storage::traits::PackedLayout,
+    )]
+    pub struct Auction {
+        /// Epoch the auction started.
+        pub start: u32,
+        /// Highest revealed bidder so far.
+        pub winner: Option<AccountId>,
+        /// Deposit of the highest revealed bid, refunded minus the price.
+        pub deposit: Balance,
+        /// Highest revealed bid.
+        pub highest: Balance,
+        /// Second highest revealed bid.
+        pub second: Balance,
+        /// `locked_deposit` when the auction started, kept by the winner.
+        pub locked_deposit: Balance,
+    }
+
+    /// Errors that can occur upon calling this contract.
+    #[cfg_attr(feature = "std", derive(::scale_info::TypeInfo))]
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, scale::Encode, scale::Decode)]
+    pub enum Error {
+        /// Caller is not the registry admin.
+        NotAdmin,
+        /// Caller is not the owner of the name.
+        NotOwner,
+        /// Caller is neither the owner, the approved account nor an operator.
+        NotApproved,
+        /// Name is not registered.
+        NameNotFound,
+        /// Operation is only allowed on top level names.
+        SubnameNotAllowed,
+        /// Sub-name still has sub-names of its own.
+        HasSubnames,
+        /// Name is past its duration (or grace period).
+        NameExpired,
+        /// Name is invalid or still owned by someone else.
+        NameNotAvailable,
+        /// Only the previous owner can renew during the grace period.
+        GracePeriodOwnerOnly,
+        /// Name is not in its grace period.
+        NotInGracePeriod,
+        /// Grace period was already announced for this expiry.
+        GracePeriodAlreadyStarted,
+        /// Transferred balance does not cover the price.
+        InsufficientPayment,
+        /// Name is short enough that it can only be won in an auction.
+        AuctionRequired,
+        /// Name is not short enough to be auctioned.
+        AuctionNotAllowed,
+        /// An auction for the name is already running.
+        AuctionInProgress,
+        /// No auction for the name.
+        AuctionNotFound,
+        /// Auction is not in its bidding period.
+        NotInBiddingPeriod,
+        /// Auction is not in its reveal period.
+        NotInRevealPeriod,
+        /// Auction reveal period did not end yet.
+        AuctionNotEnded,
+        /// A bid with the same seal already exists.
+        BidExists,
+        /// No bid for the given seal and caller.
+        BidNotFound,
+        /// Bid could still be revealed in an auction.
+        BidStillLocked,
+        /// Commitment exists and did not expire yet.
+        CommitStillValid,
+        /// Commitment is older than the allowed age.
+        CommitExpired,
+        /// Commitment is younger than the minimum age.
+        CommitTooNew,
+        /// No commitment found for the given data.
+        NoCommit,
+        /// Epoch schedule did not start yet.
+        BeforeGenesis,
+        /// Transfer would have brought the contract below subsistence threshold.
+        BelowSubsistenceThreshold,
+        /// Transfer of funds failed.
+        TransferFailed,
+        /// Name does not pass validation.
+        InvalidName(NameError),
+        /// Component is not set in the directory.
+        ComponentNotFound,
+    }
+
+    /// Reasons a name is rejected by `validate`.
+    #[cfg_attr(feature = "std", derive(::scale_info::TypeInfo))]
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, scale::Encode, scale::Decode)]
+    pub enum NameError {
+        /// Name is shorter than `min_name_length`.
+        TooShort,
+        /// Name is longer than `max_name_length`.
+        TooLong,
+        /// Name contains whitespace, control characters or a dot.
+        InvalidCharacter,
+        /// Name contains non ASCII characters while `ascii_only` is set.
+        NotAscii,
+        /// Name is on the reserved list.
+        Reserved,
+        /// Dotted name without any label.
+        Empty,
+    }
+
+    impl From<NameError> for Error {
+        fn from(err: NameError) -> Self {
+            Error::InvalidName(err)
+        }
+    }
+
+    /// Name trimmed, lowercased and NFC normalized, as hashed by the registry.
+    pub type NormalizedName = String;
+
+    /// Type alias for the contract's result type.
+    pub type Result<T> = core::result::Result<T, Error>;
 
     /// Defines the storage of your contract.
     /// Add new fields to the below struct in order
@@ -22,8 +236,22 @@ mod registry {
     pub struct Registry {
         registry: StorageHashMap<Hash, (AccountId, BlockNumber, u32)>,
         commit_name: StorageHashMap<Hash, Hash>,
-        commit: StorageHashMap<Hash, BlockNumber>,
-        epoch: EpochProxy,
+        name_commit: StorageHashMap<Hash, Hash>,
+        commit: StorageHashMap<Hash, (AccountId, BlockNumber, Balance)>,
+        names: StorageHashMap<Hash, String>,
+        primary: StorageHashMap<AccountId, Hash>,
+        approvals: StorageHashMap<Hash, AccountId>,
+        operators: StorageHashMap<(AccountId, AccountId), bool>,
+        grace_started: StorageHashMap<Hash, u32>,
+        reserved: StorageHashMap<Hash, bool>,
+        deposit: StorageHashMap<Hash, Balance>,
+        bids: StorageHashMap<Hash, (AccountId, Balance, u32)>,
+        auctions: StorageHashMap<Hash, Auction>,
+        subnames: StorageHashMap<Hash, (Hash, AccountId, BlockNumber)>,
+        children: StorageHashMap<Hash, u32>,
+        config: RegistryConfig,
+        admin: AccountId,
+        directory: Directory,
     }
 
     /// Emitted whenever a new name is being registered.
@@ -42,75 +270,437 @@ mod registry {
         name: Hash,
     }
 
+    /// Emitted whenever a registered name has its duration extended.
+    #[ink(event)]
+    pub struct Renewed {
+        #[ink(topic)]
+        name: Hash,
+        #[ink(topic)]
+        from: AccountId,
+        duration: u32,
+    }
+
+    /// Emitted whenever a name changes owner.
+    #[ink(event)]
+    pub struct Transfer {
+        #[ink(topic)]
+        name: Hash,
+        #[ink(topic)]
+        from: AccountId,
+        #[ink(topic)]
+        to: AccountId,
+    }
+
+    /// Emitted whenever an account is approved to transfer a single name.
+    #[ink(event)]
+    pub struct Approval {
+        #[ink(topic)]
+        name: Hash,
+        #[ink(topic)]
+        owner: AccountId,
+        #[ink(topic)]
+        approved: AccountId,
+    }
+
+    /// Emitted whenever an operator is enabled or disabled for an owner.
+    #[ink(event)]
+    pub struct ApprovalForAll {
+        #[ink(topic)]
+        owner: AccountId,
+        #[ink(topic)]
+        operator: AccountId,
+        approved: bool,
+    }
+
+    /// Emitted whenever a name enters its grace period.
+    #[ink(event)]
+    pub struct GracePeriodStarted {
+        #[ink(topic)]
+        name: Hash,
+        #[ink(topic)]
+        owner: AccountId,
+        until: u32,
+    }
+
+    /// Emitted whenever an account sets its primary name.
+    #[ink(event)]
+    pub struct PrimaryNameSet {
+        #[ink(topic)]
+        owner: AccountId,
+        #[ink(topic)]
+        name: Hash,
+    }
+
+    /// Emitted whenever the owner of `parent` creates a sub-name.
+    #[ink(event)]
+    pub struct SubnameCreated {
+        #[ink(topic)]
+        name: Hash,
+        #[ink(topic)]
+        parent: Hash,
+        #[ink(topic)]
+        owner: AccountId,
+    }
+
+    /// Emitted whenever the owner of `parent` deletes a sub-name.
+    #[ink(event)]
+    pub struct SubnameDeleted {
+        #[ink(topic)]
+        name: Hash,
+        #[ink(topic)]
+        parent: Hash,
+    }
+
+    /// Emitted whenever an auction for a name starts.
+    #[ink(event)]
+    pub struct AuctionStarted {
+        #[ink(topic)]
+        name: Hash,
+        start: u32,
+    }
+
+    /// Emitted whenever a sealed bid is revealed.
+    #[ink(event)]
+    pub struct BidRevealed {
+        #[ink(topic)]
+        name: Hash,
+        #[ink(topic)]
+        bidder: AccountId,
+        value: Balance,
+    }
+
+    /// Emitted whenever an auction is closed, `winner` is `None` when no
+    /// revealed bid reached the rent price.
+    #[ink(event)]
+    pub struct AuctionFinalized {
+        #[ink(topic)]
+        name: Hash,
+        #[ink(topic)]
+        winner: Option<AccountId>,
+        price: Balance,
+    }
+
+    /// Emitted whenever the admin adds or removes a reserved name.
+    #[ink(event)]
+    pub struct ReservedNameChanged {
+        #[ink(topic)]
+        name: Hash,
+        reserved: bool,
+    }
+
+    /// Emitted whenever the admin changes the registry config.
+    #[ink(event)]
+    pub struct ConfigChanged {
+        #[ink(topic)]
+        by: AccountId,
+        old: RegistryConfig,
+        new: RegistryConfig,
+    }
+
     impl Registry {
-        /// Initializes the value to the initial value.
+        /// Initializes the value to the initial value, the caller becomes the
+        /// admin allowed to change `config`.
         #[ink(constructor)]
-        pub fn new(init_value: EpochProxy) -> Self {
+        pub fn new(directory: Directory, config: RegistryConfig) -> Self {
             Self {
                 registry: Default::default(),
                 commit_name: Default::default(),
+                name_commit: Default::default(),
                 commit: Default::default(),
-                epoch: init_value,
+                names: Default::default(),
+                primary: Default::default(),
+                approvals: Default::default(),
+                operators: Default::default(),
+                grace_started: Default::default(),
+                reserved: Default::default(),
+                deposit: Default::default(),
+                bids: Default::default(),
+                auctions: Default::default(),
+                subnames: Default::default(),
+                children: Default::default(),
+                config,
+                admin: Self::env().caller(),
+                directory,
             }
         }
 
-        /// Simply returns the current Hash value of our `name`.
+        fn epoch(&self) -> Result<Epoch> {
+            resolve(&self.directory, EPOCH).ok_or(Error::ComponentNotFound)
+        }
+
+        /// Returns the hash of `name` after normalizing it, the same hash
+        /// registration stores, or why the name is not valid.
         #[ink(message)]
-        pub fn get_hash(&self, name: String) -> Hash {
+        pub fn get_hash(&self, name: String) -> Result<Hash> {
+            Ok(self.hash_name(&self.normalize(name)?))
+        }
+
+        /// Returns the hash of a dotted name given by its labels, e.g.
+        /// `["kusama", "acme"]` for `kusama.acme`. Every label is normalized
+        /// first, a single label hashes to the same value as `get_hash` and no
+        /// labels at all are rejected.
+        #[ink(message)]
+        pub fn get_node_hash(&self, labels: Vec<String>) -> Result<Hash> {
+            let mut labels = labels.into_iter().rev();
+            let mut node = match labels.next() {
+                Some(label) => self.get_hash(label)?,
+                None => return Err(NameError::Empty.into()),
+            };
+            for label in labels {
+                node = self.child_node(node, &self.normalize(label)?);
+            }
+            Ok(node)
+        }
+
+        fn hash_name(&self, name: &str) -> Hash {
             Hash::from(self.env().hash_bytes::<Blake2x256>(name.as_bytes()))
         }
 
+        fn child_node(&self, parent: Hash, label: &str) -> Hash {
+            let mut pimage: Vec<u8> = Vec::new();
+            pimage.extend_from_slice(parent.as_ref());
+            pimage.extend_from_slice(self.hash_name(label).as_ref());
+            Hash::from(self.env().hash_bytes::<Blake2x256>(&pimage))
+        }
+
         #[ink(message)]
         pub fn get_indexer_rate(&self) -> Balance {
-            Balance::from(10u32)
+            self.config.indexer_rate
         }
 
         #[ink(message)]
         pub fn valid(&self, name: String) -> bool {
+            self.validate(name).is_ok()
+        }
+
+        /// Returns the normalized form of `name`, the one being hashed and
+        /// stored, or why it cannot be registered.
+        #[ink(message)]
+        pub fn validate(&self, name: String) -> core::result::Result<NormalizedName, NameError> {
+            let name = self.normalize(name)?;
+            if self.reserved.contains_key(&self.hash_name(&name)) {
+                return Err(NameError::Reserved);
+            }
+            Ok(name)
+        }
+
+        fn normalize(&self, name: String) -> core::result::Result<NormalizedName, NameError> {
+            let name = name.trim();
+            if self.config.ascii_only && !name.is_ascii() {
+                return Err(NameError::NotAscii);
+            }
+            let name: String = name.to_lowercase().nfc().collect();
+            if name
+                .chars()
+                .any(|c| c.is_control() || c.is_whitespace() || c == '.')
+            {
+                return Err(NameError::InvalidCharacter);
+            }
             let l = name.as_bytes().len();
             ink_env::debug_println!("name size {} is {}", name, l);
-            l <= 256
+            if l < self.config.min_name_length as usize {
+                return Err(NameError::TooShort);
+            }
+            if l > self.config.max_name_length as usize {
+                return Err(NameError::TooLong);
+            }
+            Ok(name)
         }
 
         #[ink(message)]
         pub fn available(&self, name: String) -> bool {
-            if self.valid(name.clone()) {
-                let h = self.get_hash(name);
-                if let Some((_, ref b, ref d)) = self.registry.get(&h) {
-                    let epoch = self.get_current_epoch();
-                    let t = epoch - *b;
-                    ink_env::debug_println!("available: {} == {}", t, *d);
-                    *d < t
-                } else {
-                    true
-                }
+            match self.get_current_epoch() {
+                Some(epoch) => self.available_at(name, epoch),
+                None => false,
+            }
+        }
+
+        fn available_at(&self, name: String, epoch: u32) -> bool {
+            if let Ok(name) = self.validate(name) {
+                let h = self.hash_name(&name);
+                let status = self.status_at(h, epoch);
+                ink_env::debug_println!("available: {:?}", status);
+                status == NameStatus::Expired || status == NameStatus::Never
             } else {
                 false
             }
         }
 
+        #[ink(message)]
+        pub fn is_reserved(&self, name: String) -> bool {
+            match self.normalize(name) {
+                Ok(name) => self
+                    .reserved
+                    .get(&self.hash_name(&name))
+                    .cloned()
+                    .unwrap_or(false),
+                Err(_) => false,
+            }
+        }
+
+        /// Adds or removes a name from the reserved list, only the admin can
+        /// call it. Reserved names cannot be committed or registered.
+        #[ink(message)]
+        pub fn set_reserved(&mut self, name: String, reserved: bool) -> Result<()> {
+            if self.env().caller() != self.admin {
+                return Err(Error::NotAdmin);
+            }
+            let name_hash = self.hash_name(&self.normalize(name)?);
+            if reserved {
+                self.reserved.insert(name_hash, true);
+            } else {
+                let _ = self.reserved.take(&name_hash);
+            }
+            self.env().emit_event(ReservedNameChanged {
+                name: name_hash,
+                reserved,
+            });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_config(&self) -> RegistryConfig {
+            self.config.clone()
+        }
+
+        #[ink(message)]
+        pub fn set_indexer_rate(&mut self, indexer_rate: Balance) -> Result<()> {
+            self.update_config(|config| config.indexer_rate = indexer_rate)
+        }
+
+        #[ink(message)]
+        pub fn set_commit_fee(&mut self, commit_fee: Balance) -> Result<()> {
+            self.update_config(|config| config.commit_fee = commit_fee)
+        }
+
+        /// Only applies to new registrations, names keep the deposit they
+        /// locked when registered.
+        #[ink(message)]
+        pub fn set_locked_deposit(&mut self, locked_deposit: Balance) -> Result<()> {
+            self.update_config(|config| config.locked_deposit = locked_deposit)
+        }
+
+        #[ink(message)]
+        pub fn set_min_commit_age(&mut self, min_commit_age: BlockNumber) -> Result<()> {
+            self.update_config(|config| config.min_commit_age = min_commit_age)
+        }
+
+        #[ink(message)]
+        pub fn set_max_commit_age(&mut self, max_commit_age: BlockNumber) -> Result<()> {
+            self.update_config(|config| config.max_commit_age = max_commit_age)
+        }
+
+        #[ink(message)]
+        pub fn set_min_name_length(&mut self, min_name_length: u32) -> Result<()> {
+            self.update_config(|config| config.min_name_length = min_name_length)
+        }
+
+        #[ink(message)]
+        pub fn set_ascii_only(&mut self, ascii_only: bool) -> Result<()> {
+            self.update_config(|config| config.ascii_only = ascii_only)
+        }
+
+        #[ink(message)]
+        pub fn set_max_name_length(&mut self, max_name_length: u32) -> Result<()> {
+            self.update_config(|config| config.max_name_length = max_name_length)
+        }
+
+        #[ink(message)]
+        pub fn set_rent_price(&mut self, rent_price: Balance) -> Result<()> {
+            self.update_config(|config| config.rent_price = rent_price)
+        }
+
+        #[ink(message)]
+        pub fn set_grace_period(&mut self, grace_period: u32) -> Result<()> {
+            self.update_config(|config| config.grace_period = grace_period)
+        }
+
+        #[ink(message)]
+        pub fn set_auction_below_length(&mut self, auction_below_length: u32) -> Result<()> {
+            self.update_config(|config| config.auction_below_length = auction_below_length)
+        }
+
+        #[ink(message)]
+        pub fn set_auction_periods(
+            &mut self,
+            bidding_epochs: u32,
+            reveal_epochs: u32,
+            auction_duration: u32,
+        ) -> Result<()> {
+            self.update_config(|config| {
+                config.bidding_epochs = bidding_epochs;
+                config.reveal_epochs = reveal_epochs;
+                config.auction_duration = auction_duration;
+            })
+        }
+
+        fn update_config<F: FnOnce(&mut RegistryConfig)>(&mut self, update: F) -> Result<()> {
+            let caller = self.env().caller();
+            if caller != self.admin {
+                ink_env::debug_println!("not the admin");
+                return Err(Error::NotAdmin);
+            }
+            let old = self.config.clone();
+            update(&mut self.config);
+            self.env().emit_event(ConfigChanged {
+                by: caller,
+                old,
+                new: self.config.clone(),
+            });
+            Ok(())
+        }
+
+        /// Returns the commitment for the normalized form of `name`. A `u32`
+        /// secret can be brute forced, prefer `make_commitment_with_secret`.
         #[ink(message, selector = 0x1EECBEEF)]
-        pub fn make_commitment(&self, name: String, owner: AccountId, secret: u32) -> Hash {
+        pub fn make_commitment(&self, name: String, owner: AccountId, secret: u32) -> Result<Hash> {
+            self.commitment_for(name, owner, &secret.to_be_bytes())
+        }
+
+        /// Returns the commitment for the normalized form of `name` using a
+        /// 32 bytes secret.
+        #[ink(message)]
+        pub fn make_commitment_with_secret(
+            &self,
+            name: String,
+            owner: AccountId,
+            secret: Hash,
+        ) -> Result<Hash> {
+            self.commitment_for(name, owner, secret.as_ref())
+        }
+
+        fn commitment_for(&self, name: String, owner: AccountId, secret: &[u8]) -> Result<Hash> {
+            let name = self.validate(name)?;
             let mut out = [0; 32];
             let mut pimage: Vec<u8> = Vec::new();
             pimage.extend_from_slice(name.as_bytes());
             let enc_owner = owner.encode();
             pimage.extend_from_slice(&enc_owner[..]);
-            pimage.extend_from_slice(&secret.to_be_bytes());
+            pimage.extend_from_slice(secret);
             ink_env::debug_println!("preimage: {:?}", pimage);
             ink_env::hash_bytes::<Blake2x256>(&pimage, &mut out);
             ink_env::debug_println!("commitment: {:?}", out);
-            Hash::from(out)
+            Ok(Hash::from(out))
         }
 
         #[ink(message)]
         pub fn not_expired(&self, hash_name: Hash) -> Option<(AccountId, BlockNumber, u32)> {
-            if let Some((ref a, ref b, ref d)) = self.registry.get(&hash_name) {
-                let epoch = self.get_current_epoch();
-                let t = epoch - *b;
-                ink_env::debug_println!("not_expired: {} == {}", t, *d);
-                if t <= *d {
-                    Some((a.clone(), b.clone(), d.clone()))
+            self.not_expired_at(hash_name, self.get_current_epoch()?)
+        }
+
+        fn not_expired_at(
+            &self,
+            hash_name: Hash,
+            epoch: u32,
+        ) -> Option<(AccountId, BlockNumber, u32)> {
+            if let Some((a, b, d)) = self.record(hash_name) {
+                if epoch < b {
+                    return None;
+                }
+                let t = epoch - b;
+                ink_env::debug_println!("not_expired: {} == {}", t, d);
+                if t <= d {
+                    Some((a, b, d))
                 } else {
                     None
                 }
@@ -119,174 +709,883 @@ mod registry {
             }
         }
 
+        /// Returns owner, start and duration of a name regardless of expiry.
+        /// Sub-names take start and duration from their top level name and
+        /// are gone once it is unregistered or registered again.
+        fn record(&self, hash_name: Hash) -> Option<(AccountId, BlockNumber, u32)> {
+            if let Some(entry) = self.registry.get(&hash_name) {
+                return Some(*entry);
+            }
+            let (parent, owner, start) = self.subnames.get(&hash_name).cloned()?;
+            match self.record(parent) {
+                Some((_o, b, d)) if b == start => Some((owner, start, d)),
+                _ => None,
+            }
+        }
+
         #[ink(message)]
-        pub fn expired(&self, hash_name: Hash) -> Option<(AccountId, BlockNumber, u32)> {
-            if let Some((ref a, ref b, ref d)) = self.registry.get(&hash_name) {
-                let epoch = self.get_current_epoch();
-                let t = epoch - *b;
-                ink_env::debug_println!("expired: {} == {}", t, *d);
-                if t > *d {
-                    Some((a.clone(), b.clone(), d.clone()))
+        pub fn status(&self, hash_name: Hash) -> NameStatus {
+            match self.get_current_epoch() {
+                Some(epoch) => self.status_at(hash_name, epoch),
+                None => NameStatus::Never,
+            }
+        }
+
+        fn status_at(&self, hash_name: Hash, epoch: u32) -> NameStatus {
+            if let Some((_, b, d)) = self.record(hash_name) {
+                if epoch < b {
+                    // the current registration did not start yet
+                    return NameStatus::Never;
+                }
+                let t = epoch - b;
+                ink_env::debug_println!("status: {} == {}", t, d);
+                if t <= d {
+                    NameStatus::Active
+                } else if t - d <= self.config.grace_period {
+                    NameStatus::Grace
                 } else {
-                    None
+                    NameStatus::Expired
                 }
+            } else if self.subnames.contains_key(&hash_name) {
+                NameStatus::Expired
             } else {
-                None
+                NameStatus::Never
+            }
+        }
+
+        /// Announces that a name entered its grace period, callable by anyone
+        /// once per expiry.
+        #[ink(message)]
+        pub fn start_grace_period(&mut self, name_hash: Hash) -> Result<()> {
+            let epoch = self.current_epoch()?;
+            self.start_grace_period_at(name_hash, epoch)
+        }
+
+        fn start_grace_period_at(&mut self, name_hash: Hash, epoch: u32) -> Result<()> {
+            if self.status_at(name_hash, epoch) != NameStatus::Grace {
+                return Err(Error::NotInGracePeriod);
+            }
+            let (owner, start, duration) = self
+                .registry
+                .get(&name_hash)
+                .cloned()
+                .ok_or(Error::NameNotFound)?;
+            let expiry = start.saturating_add(duration);
+            if self.grace_started.get(&name_hash) == Some(&expiry) {
+                return Err(Error::GracePeriodAlreadyStarted);
             }
+            self.grace_started.insert(name_hash, expiry);
+            self.env().emit_event(GracePeriodStarted {
+                name: name_hash,
+                owner,
+                until: expiry.saturating_add(self.config.grace_period),
+            });
+            Ok(())
         }
 
         fn not_expired_commit(&self, b: &BlockNumber) -> bool {
-            let t = self.env().block_number() - *b;
+            let t = self.env().block_number().saturating_sub(*b);
             ink_env::debug_println!("not_expired_commit: {}", t);
-            t <= 100
+            t <= self.config.max_commit_age
         }
 
-        fn commit_check(&self, commitment: &Hash) {
-            if let Some(ref b) = self.commit.get(commitment) {
+        fn commit_check(&self, commitment: &Hash) -> Result<()> {
+            if let Some((_c, ref b, _f)) = self.commit.get(commitment) {
                 let valid = self.not_expired_commit(b);
                 ink_env::debug_println!("commit still valid: {}", valid);
-                assert!(valid, "commit still valid");
+                if valid {
+                    return Err(Error::CommitStillValid);
+                }
             }
             if let Some(name_hash) = self.commit_name.get(commitment) {
-                let expired = self.expired(*name_hash).is_some();
+                let expired = self.status(*name_hash) == NameStatus::Expired;
                 ink_env::debug_println!("name avaliable: {}", expired);
-                assert!(expired, "name not avaliable");
+                if !expired {
+                    return Err(Error::NameNotAvailable);
+                }
             }
+            Ok(())
         }
 
-        fn commit_register(&self, commitment: &Hash) {
-            if let Some(ref b) = self.commit.get(commitment) {
+        fn commit_register(&self, commitment: &Hash) -> Result<()> {
+            if let Some((_c, ref b, _f)) = self.commit.get(commitment) {
                 let not_expired_commit = self.not_expired_commit(b);
                 ink_env::debug_println!("commit not expired: {}", not_expired_commit);
-                assert!(not_expired_commit, "commit expired");
+                if !not_expired_commit {
+                    Err(Error::CommitExpired)
+                } else if self.env().block_number().saturating_sub(*b) < self.config.min_commit_age
+                {
+                    Err(Error::CommitTooNew)
+                } else {
+                    Ok(())
+                }
             } else {
                 ink_env::debug_println!("no commit");
-                panic!("no commit")
+                Err(Error::NoCommit)
             }
         }
 
         #[ink(message, payable, selector = 0xDEADBEEF)]
-        pub fn commit(&mut self, commitment: Hash) {
+        pub fn commit(&mut self, commitment: Hash) -> Result<()> {
             ink_env::debug_println!(
                 "received commit payment: {}",
                 self.env().transferred_balance()
             );
-            assert!(
-                self.env().transferred_balance() >= 10,
-                "mininum payment is ten"
+            if self.env().transferred_balance() < self.config.commit_fee {
+                return Err(Error::InsufficientPayment);
+            }
+            self.commit_check(&commitment)?;
+            self.refund_commit(&commitment)?;
+            self.commit.insert(
+                commitment,
+                (
+                    self.env().caller(),
+                    self.env().block_number(),
+                    self.env().transferred_balance(),
+                ),
             );
-            self.commit_check(&commitment);
-            self.commit.insert(commitment, self.env().block_number());
             ink_env::debug_println!("commited");
+            Ok(())
+        }
+
+        /// Returns the fee of an expired and unused commitment to the account
+        /// that made it, callable by anyone.
+        #[ink(message)]
+        pub fn reclaim_commit(&mut self, commitment: Hash) -> Result<()> {
+            match self.commit.get(&commitment) {
+                Some((_c, b, _f)) if !self.not_expired_commit(b) => self.refund_commit(&commitment),
+                Some(_) => Err(Error::CommitStillValid),
+                None => Err(Error::NoCommit),
+            }
+        }
+
+        fn refund_commit(&mut self, commitment: &Hash) -> Result<()> {
+            if let Some((committer, _b, fee)) = self.commit.get(commitment).cloned() {
+                self.transfer_balance(committer, fee)?;
+                let _ = self.commit.take(commitment);
+            }
+            Ok(())
+        }
+
+        fn transfer_balance(&self, to: AccountId, value: Balance) -> Result<()> {
+            self.env().transfer(to, value).map_err(|err| match err {
+                ink_env::Error::BelowSubsistenceThreshold => Error::BelowSubsistenceThreshold,
+                _ => Error::TransferFailed,
+            })
         }
 
-        fn unlock_balance(&mut self, name_hash: Hash) {
+        fn unlock_balance(&mut self, name_hash: Hash) -> Result<()> {
             if let Some((ref a, _b, _d)) = self.registry.get(&name_hash) {
-                match self.env().transfer(*a, 10) {
-                    Err(ink_env::Error::BelowSubsistenceThreshold) => {
-                        panic!(
-                            "requested transfer would have brought contract\
-                            below subsistence threshold!"
-                        )
-                    }
-                    Err(_) => panic!("transfer failed!"),
-                    Ok(_) => {}
-                }
+                let deposit = self.deposit.get(&name_hash).cloned().unwrap_or_default();
+                self.transfer_balance(*a, deposit)?;
             }
+            let _ = self.deposit.take(&name_hash);
             let _ = self.registry.take(&name_hash);
-            let mut cn_values = self.commit_name.iter();
-            let entry = cn_values.next();
-            let mut nh = Hash::default();
-            while entry.is_some() {
-                if let Some(ref inner) = entry {
-                    if *inner.1 == name_hash {
-                        nh = *inner.0;
-                        break;
-                    }
-                }
+            let _ = self.names.take(&name_hash);
+            let _ = self.approvals.take(&name_hash);
+            let _ = self.grace_started.take(&name_hash);
+            if let Some(commitment) = self.name_commit.take(&name_hash) {
+                let _ = self.commit_name.take(&commitment);
             }
-            if nh != Hash::default() {
-                let _ = self.commit_name.take(&nh);
+            Ok(())
+        }
+
+        /// Records the commitment used to register `name_hash`, keeping
+        /// `commit_name` and its reverse `name_commit` in sync.
+        fn link_commit(&mut self, commitment: Hash, name_hash: Hash) {
+            if let Some(previous) = self.name_commit.insert(name_hash, commitment) {
+                let _ = self.commit_name.take(&previous);
             }
+            self.commit_name.insert(commitment, name_hash);
         }
 
         #[ink(message)]
         pub fn rent_price(&self, name: String, duration: u32) -> Balance {
-            let rp = name.as_bytes().len() as u32 * duration * 1_000;
+            let rp = self.rent_price_for(name.as_bytes().len() as u32, duration);
             ink_env::debug_println!("rent_price {} for {} is {}", name, duration, rp);
-            Balance::from(rp)
+            rp
+        }
+
+        fn rent_price_for(&self, length: u32, duration: u32) -> Balance {
+            Balance::from(length) * Balance::from(duration) * self.config.rent_price
         }
 
         #[ink(message)]
-        pub fn get_current_epoch(&self) -> u32 {
-            self.epoch.get().get_current_epoch()
+        pub fn get_current_epoch(&self) -> Option<u32> {
+            self.epoch().ok()?.get_current_epoch()
+        }
+
+        fn current_epoch(&self) -> Result<u32> {
+            self.epoch()?
+                .get_current_epoch()
+                .ok_or(Error::BeforeGenesis)
         }
 
         #[ink(message, payable, selector = 0xCAFEBABE)]
-        pub fn register(&mut self, name: String, from: AccountId, duration: u32, secret: u32) {
+        pub fn register(
+            &mut self,
+            name: String,
+            from: AccountId,
+            duration: u32,
+            secret: u32,
+        ) -> Result<()> {
+            self.register_with(name, from, duration, &secret.to_be_bytes())
+        }
+
+        /// Registers a name committed with `make_commitment_with_secret`.
+        #[ink(message, payable)]
+        pub fn register_with_secret(
+            &mut self,
+            name: String,
+            from: AccountId,
+            duration: u32,
+            secret: Hash,
+        ) -> Result<()> {
+            self.register_with(name, from, duration, secret.as_ref())
+        }
+
+        fn register_with(
+            &mut self,
+            name: String,
+            from: AccountId,
+            duration: u32,
+            secret: &[u8],
+        ) -> Result<()> {
+            let epoch = self.current_epoch()?;
+            self.register_at(name, from, duration, secret, epoch)
+        }
+
+        fn register_at(
+            &mut self,
+            name: String,
+            from: AccountId,
+            duration: u32,
+            secret: &[u8],
+            epoch: u32,
+        ) -> Result<()> {
+            let name = self.validate(name)?;
             let p = self.env().transferred_balance();
             ink_env::debug_println!("register payment: {}", p);
             let r = self.rent_price(name.clone(), duration);
             ink_env::debug_println!("rent price: {}", r);
-            assert!(
-                p >= r + 10,
-                "payment was not enough for rent plus 10 (locked balance)"
-            );
-            let commitment = self.make_commitment(name.clone(), from, secret);
+            let locked_deposit = self.config.locked_deposit;
+            if p < r + locked_deposit {
+                return Err(Error::InsufficientPayment);
+            }
+            let commitment = self.commitment_for(name.clone(), from, secret)?;
             ink_env::debug_println!("commitment: {:?}", commitment);
-            self.commit_register(&commitment);
+            self.commit_register(&commitment)?;
             ink_env::debug_println!("name: {:?}", name);
-            let available = self.available(name.clone());
+            let available = self.available_at(name.clone(), epoch);
             ink_env::debug_println!("avaliable: {:?}", available);
-            assert!(available, "not available");
-            let name_hash = self.get_hash(name);
+            if !available {
+                return Err(Error::NameNotAvailable);
+            }
+            if (name.as_bytes().len() as u32) < self.config.auction_below_length {
+                ink_env::debug_println!("auction required");
+                return Err(Error::AuctionRequired);
+            }
+            let name_hash = self.hash_name(&name);
             ink_env::debug_println!("name_hash: {:?}", name_hash);
-            if let Some((_a, _b, _d)) = self.expired(name_hash) {
-                self.unregister_unchecked(name_hash);
+            self.register_unchecked(name_hash, name, from, epoch, duration, locked_deposit)?;
+            self.link_commit(commitment, name_hash);
+            self.refund_commit(&commitment)
+        }
+
+        fn register_unchecked(
+            &mut self,
+            name_hash: Hash,
+            name: String,
+            from: AccountId,
+            epoch: u32,
+            duration: u32,
+            locked_deposit: Balance,
+        ) -> Result<()> {
+            if self.status_at(name_hash, epoch) == NameStatus::Expired {
+                self.unregister_unchecked(name_hash)?;
             }
-            let epoch = self.get_current_epoch();
             self.registry.insert(name_hash, (from, epoch, duration));
-            self.commit_name.insert(commitment, name_hash);
-            self.commit.take(&commitment);
+            self.names.insert(name_hash, name);
+            self.deposit.insert(name_hash, locked_deposit);
+            let _ = self.approvals.take(&name_hash);
             self.env().emit_event(Register {
                 name: name_hash,
                 from,
             });
+            Ok(())
         }
 
-        fn unregister_unchecked(&mut self, name: Hash) {
-            ink_env::debug_println!("unregister payment: {}", self.env().transferred_balance());
-            self.unlock_balance(name);
-            self.env().emit_event(Unregister { name });
+        /// Seals a bid of `value` for `name`, built on top of the commitment
+        /// of `make_commitment` so the name stays hidden until reveal.
+        #[ink(message)]
+        pub fn make_bid(
+            &self,
+            name: String,
+            owner: AccountId,
+            value: Balance,
+            secret: u32,
+        ) -> Result<Hash> {
+            let commitment = self.make_commitment(name, owner, secret)?;
+            let mut out = [0; 32];
+            let mut pimage: Vec<u8> = Vec::new();
+            pimage.extend_from_slice(commitment.as_ref());
+            pimage.extend_from_slice(&value.to_be_bytes());
+            ink_env::hash_bytes::<Blake2x256>(&pimage, &mut out);
+            Ok(Hash::from(out))
         }
 
         #[ink(message)]
-        pub fn is_owner(&self, name: Hash) -> bool {
-            if let Some((ref o, _b, _d)) = self.registry.get(&name) {
-                self.env().caller() == *o
-            } else {
-                false
-            }
+        pub fn get_auction(&self, name_hash: Hash) -> Option<Auction> {
+            self.auctions.get(&name_hash).cloned()
         }
 
+        /// Opens an auction for an available name shorter than
+        /// `auction_below_length`.
         #[ink(message)]
-        pub fn is_owner_from(&self, name: Hash, from: AccountId) -> bool {
-            if let Some((ref o, _b, _d)) = self.registry.get(&name) {
-                from == *o
-            } else {
-                false
-            }
+        pub fn start_auction(&mut self, name: String) -> Result<()> {
+            let epoch = self.current_epoch()?;
+            self.start_auction_at(name, epoch)
+        }
+
+        fn start_auction_at(&mut self, name: String, epoch: u32) -> Result<()> {
+            let name = self.validate(name)?;
+            if name.as_bytes().len() as u32 >= self.config.auction_below_length {
+                return Err(Error::AuctionNotAllowed);
+            }
+            let name_hash = self.hash_name(&name);
+            match self.status_at(name_hash, epoch) {
+                NameStatus::Expired | NameStatus::Never => {}
+                _ => return Err(Error::NameNotAvailable),
+            }
+            if self.auctions.contains_key(&name_hash) {
+                return Err(Error::AuctionInProgress);
+            }
+            self.auctions.insert(
+                name_hash,
+                Auction {
+                    start: epoch,
+                    winner: None,
+                    deposit: 0,
+                    highest: 0,
+                    second: 0,
+                    locked_deposit: self.config.locked_deposit,
+                },
+            );
+            self.env().emit_event(AuctionStarted {
+                name: name_hash,
+                start: epoch,
+            });
+            Ok(())
+        }
+
+        /// Places a sealed bid built with `make_bid` in the running auction
+        /// of `name_hash`. The transferred balance must exceed the locked
+        /// deposit and cover the bid plus the locked deposit when revealed.
+        #[ink(message, payable)]
+        pub fn bid(&mut self, name_hash: Hash, sealed: Hash) -> Result<()> {
+            let epoch = self.current_epoch()?;
+            self.bid_at(name_hash, sealed, epoch)
+        }
+
+        fn bid_at(&mut self, name_hash: Hash, sealed: Hash, epoch: u32) -> Result<()> {
+            let auction = self
+                .auctions
+                .get(&name_hash)
+                .cloned()
+                .ok_or(Error::AuctionNotFound)?;
+            if epoch < auction.start
+                || epoch >= auction.start.saturating_add(self.config.bidding_epochs)
+            {
+                return Err(Error::NotInBiddingPeriod);
+            }
+            if self.bids.contains_key(&sealed) {
+                return Err(Error::BidExists);
+            }
+            let deposit = self.env().transferred_balance();
+            if deposit <= auction.locked_deposit {
+                return Err(Error::InsufficientPayment);
+            }
+            // bound to the auction start, so it cannot be revealed in a later
+            // auction of the same name
+            self.bids
+                .insert(sealed, (self.env().caller(), deposit, auction.start));
+            Ok(())
+        }
+
+        /// Reveals a bid during the reveal period of the auction. Bids lower
+        /// than the current highest are refunded right away.
+        #[ink(message)]
+        pub fn reveal_bid(&mut self, name: String, value: Balance, secret: u32) -> Result<()> {
+            let epoch = self.current_epoch()?;
+            self.reveal_bid_at(name, value, secret, epoch)
+        }
+
+        fn reveal_bid_at(
+            &mut self,
+            name: String,
+            value: Balance,
+            secret: u32,
+            epoch: u32,
+        ) -> Result<()> {
+            let caller = self.env().caller();
+            let name = self.validate(name)?;
+            let sealed = self.make_bid(name.clone(), caller, value, secret)?;
+            let name_hash = self.hash_name(&name);
+            let mut auction = self
+                .auctions
+                .get(&name_hash)
+                .cloned()
+                .ok_or(Error::AuctionNotFound)?;
+            let reveal_start = auction.start.saturating_add(self.config.bidding_epochs);
+            if epoch < reveal_start
+                || epoch >= reveal_start.saturating_add(self.config.reveal_epochs)
+            {
+                return Err(Error::NotInRevealPeriod);
+            }
+            let (bidder, deposit, on) = match self.bids.get(&sealed) {
+                Some(bid) if bid.0 == caller && bid.2 == auction.start => *bid,
+                _ => return Err(Error::BidNotFound),
+            };
+            if value.saturating_add(auction.locked_deposit) > deposit {
+                return Err(Error::InsufficientPayment);
+            }
+            ink_env::debug_println!("reveal bid {} placed in auction {}", value, on);
+            if value > auction.highest {
+                if let Some(previous) = auction.winner {
+                    self.transfer_balance(previous, auction.deposit)?;
+                }
+                auction.second = auction.highest;
+                auction.highest = value;
+                auction.winner = Some(bidder);
+                auction.deposit = deposit;
+            } else {
+                self.transfer_balance(bidder, deposit)?;
+                if value > auction.second {
+                    auction.second = value;
+                }
+            }
+            let _ = self.bids.take(&sealed);
+            self.auctions.insert(name_hash, auction);
+            self.env().emit_event(BidRevealed {
+                name: name_hash,
+                bidder,
+                value,
+            });
+            Ok(())
+        }
+
+        /// Closes an auction after its reveal period, registering the name
+        /// to the highest bidder at the second highest price, never lower
+        /// than the rent price for `auction_duration`. When the name was
+        /// registered meanwhile or no longer requires an auction the highest
+        /// bidder is refunded instead.
+        #[ink(message)]
+        pub fn finalize_auction(&mut self, name: String) -> Result<()> {
+            let epoch = self.current_epoch()?;
+            self.finalize_auction_at(name, epoch)
+        }
+
+        fn finalize_auction_at(&mut self, name: String, epoch: u32) -> Result<()> {
+            let name = self.normalize(name)?;
+            let length = name.as_bytes().len() as u32;
+            let name_hash = self.hash_name(&name);
+            let auction = self
+                .auctions
+                .get(&name_hash)
+                .cloned()
+                .ok_or(Error::AuctionNotFound)?;
+            let end = auction
+                .start
+                .saturating_add(self.config.bidding_epochs)
+                .saturating_add(self.config.reveal_epochs);
+            if epoch < end {
+                return Err(Error::AuctionNotEnded);
+            }
+            let duration = self.config.auction_duration;
+            let locked_deposit = auction.locked_deposit;
+            let reserve = self.rent_price_for(length, duration);
+            let price = auction.second.max(reserve);
+            let eligible =
+                length < self.config.auction_below_length && self.available_at(name.clone(), epoch);
+            let winner = match auction.winner {
+                Some(winner) if eligible && auction.highest >= reserve => {
+                    let refund = auction
+                        .deposit
+                        .checked_sub(price)
+                        .and_then(|rest| rest.checked_sub(locked_deposit))
+                        .ok_or(Error::InsufficientPayment)?;
+                    self.transfer_balance(winner, refund)?;
+                    self.register_unchecked(
+                        name_hash,
+                        name,
+                        winner,
+                        epoch,
+                        duration,
+                        locked_deposit,
+                    )?;
+                    Some(winner)
+                }
+                Some(bidder) => {
+                    self.transfer_balance(bidder, auction.deposit)?;
+                    None
+                }
+                None => None,
+            };
+            let _ = self.auctions.take(&name_hash);
+            self.env().emit_event(AuctionFinalized {
+                name: name_hash,
+                winner,
+                price: if winner.is_some() { price } else { 0 },
+            });
+            Ok(())
+        }
+
+        /// Refunds a bid that was never revealed once the auction it was
+        /// placed in has ended.
+        #[ink(message)]
+        pub fn cancel_bid(&mut self, sealed: Hash) -> Result<()> {
+            let epoch = self.current_epoch()?;
+            self.cancel_bid_at(sealed, epoch)
+        }
+
+        fn cancel_bid_at(&mut self, sealed: Hash, epoch: u32) -> Result<()> {
+            let caller = self.env().caller();
+            let (bidder, deposit, on) = match self.bids.get(&sealed) {
+                Some(bid) if bid.0 == caller => *bid,
+                _ => return Err(Error::BidNotFound),
+            };
+            if epoch
+                < on.saturating_add(self.config.bidding_epochs)
+                    .saturating_add(self.config.reveal_epochs)
+            {
+                return Err(Error::BidStillLocked);
+            }
+            self.transfer_balance(bidder, deposit)?;
+            let _ = self.bids.take(&sealed);
+            Ok(())
+        }
+
+        fn unregister_unchecked(&mut self, name: Hash) -> Result<()> {
+            ink_env::debug_println!("unregister payment: {}", self.env().transferred_balance());
+            self.unlock_balance(name)?;
+            self.env().emit_event(Unregister { name });
+            Ok(())
+        }
+
+        /// Extends the duration of a registered name by `extra_epochs`.
+        /// Anyone may pay for the renewal, the name keeps its current owner.
+        /// During the grace period only the owner can renew.
+        #[ink(message, payable, selector = 0xBEEFCAFE)]
+        pub fn renew(&mut self, name_hash: Hash, extra_epochs: u32) -> Result<()> {
+            let epoch = self.current_epoch()?;
+            self.renew_at(name_hash, extra_epochs, epoch)
+        }
+
+        fn renew_at(&mut self, name_hash: Hash, extra_epochs: u32, epoch: u32) -> Result<()> {
+            if self.subnames.contains_key(&name_hash) {
+                ink_env::debug_println!("sub-names follow their top level name");
+                return Err(Error::SubnameNotAllowed);
+            }
+            let p = self.env().transferred_balance();
+            ink_env::debug_println!("renew payment: {}", p);
+            let status = self.status_at(name_hash, epoch);
+            let (owner, start, duration) = match status {
+                NameStatus::Active | NameStatus::Grace => self
+                    .registry
+                    .get(&name_hash)
+                    .cloned()
+                    .ok_or(Error::NameNotFound)?,
+                _ => {
+                    ink_env::debug_println!("name expired");
+                    return Err(Error::NameExpired);
+                }
+            };
+            if status == NameStatus::Grace && self.env().caller() != owner {
+                ink_env::debug_println!("only the owner can renew during grace period");
+                return Err(Error::GracePeriodOwnerOnly);
+            }
+            let length = self
+                .names
+                .get(&name_hash)
+                .map(|name| name.as_bytes().len() as u32)
+                .unwrap_or_default();
+            let r = self.rent_price_for(length, extra_epochs);
+            ink_env::debug_println!("renew price: {}", r);
+            if p < r {
+                return Err(Error::InsufficientPayment);
+            }
+            let duration = duration.saturating_add(extra_epochs);
+            self.registry.insert(name_hash, (owner, start, duration));
+            let _ = self.grace_started.take(&name_hash);
+            self.env().emit_event(Renewed {
+                name: name_hash,
+                from: self.env().caller(),
+                duration,
+            });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_owner(&self, name: Hash) -> bool {
+            if let Some((o, _b, _d)) = self.record(name) {
+                self.env().caller() == o
+            } else {
+                false
+            }
+        }
+
+        /// Returns the plaintext of a registered name.
+        #[ink(message)]
+        pub fn name_of(&self, name_hash: Hash) -> Option<String> {
+            self.names.get(&name_hash).cloned()
+        }
+
+        /// Sets the name shown for the caller, the caller must own the name
+        /// and it must not be expired.
+        #[ink(message)]
+        pub fn set_primary_name(&mut self, name_hash: Hash) -> Result<()> {
+            let epoch = self.current_epoch()?;
+            self.set_primary_name_at(name_hash, epoch)
+        }
+
+        fn set_primary_name_at(&mut self, name_hash: Hash, epoch: u32) -> Result<()> {
+            let caller = self.env().caller();
+            let (owner, _b, _d) = self
+                .not_expired_at(name_hash, epoch)
+                .ok_or(Error::NameExpired)?;
+            if owner != caller {
+                ink_env::debug_println!("not the owner");
+                return Err(Error::NotOwner);
+            }
+            self.primary.insert(caller, name_hash);
+            self.env().emit_event(PrimaryNameSet {
+                owner: caller,
+                name: name_hash,
+            });
+            Ok(())
+        }
+
+        /// Returns the primary name of `owner` while it still owns it.
+        #[ink(message)]
+        pub fn primary_name(&self, owner: AccountId) -> Option<Hash> {
+            self.primary_name_at(owner, self.get_current_epoch()?)
+        }
+
+        fn primary_name_at(&self, owner: AccountId, epoch: u32) -> Option<Hash> {
+            let name_hash = self.primary.get(&owner).cloned()?;
+            match self.not_expired_at(name_hash, epoch) {
+                Some((o, _b, _d)) if o == owner => Some(name_hash),
+                _ => None,
+            }
+        }
+
+        /// Creates `label` under `parent` for `owner`, the caller must own
+        /// `parent` and it must not be expired. Sub-names expire together with
+        /// their top level name and can be nested.
+        #[ink(message)]
+        pub fn create_subname(
+            &mut self,
+            parent: Hash,
+            label: String,
+            owner: AccountId,
+        ) -> Result<Hash> {
+            let epoch = self.current_epoch()?;
+            self.create_subname_at(parent, label, owner, epoch)
+        }
+
+        fn create_subname_at(
+            &mut self,
+            parent: Hash,
+            label: String,
+            owner: AccountId,
+            epoch: u32,
+        ) -> Result<Hash> {
+            let (parent_owner, start, _d) = self
+                .not_expired_at(parent, epoch)
+                .ok_or(Error::NameExpired)?;
+            if self.env().caller() != parent_owner {
+                ink_env::debug_println!("not the owner of the parent");
+                return Err(Error::NotOwner);
+            }
+            let label = self.normalize(label)?;
+            let mut name = label.clone();
+            name.push('.');
+            name.push_str(&self.names.get(&parent).cloned().unwrap_or_default());
+            if name.as_bytes().len() > self.config.max_name_length as usize {
+                return Err(Error::InvalidName(NameError::TooLong));
+            }
+            let name_hash = self.child_node(parent, &label);
+            if self.record(name_hash).is_some() {
+                return Err(Error::NameNotAvailable);
+            }
+            self.subnames.insert(name_hash, (parent, owner, start));
+            self.names.insert(name_hash, name);
+            let _ = self.children.take(&name_hash);
+            let _ = self.approvals.take(&name_hash);
+            *self.children.entry(parent).or_insert(0) += 1;
+            self.env().emit_event(SubnameCreated {
+                name: name_hash,
+                parent,
+                owner,
+            });
+            Ok(name_hash)
+        }
+
+        /// Deletes a sub-name, the caller must own its non expired parent and
+        /// the sub-name must not have sub-names of its own.
+        #[ink(message)]
+        pub fn delete_subname(&mut self, name_hash: Hash) -> Result<()> {
+            let epoch = self.current_epoch()?;
+            self.delete_subname_at(name_hash, epoch)
+        }
+
+        fn delete_subname_at(&mut self, name_hash: Hash, epoch: u32) -> Result<()> {
+            let (parent, _o, _start) = self
+                .subnames
+                .get(&name_hash)
+                .cloned()
+                .ok_or(Error::NameNotFound)?;
+            let (parent_owner, _b, _d) = self
+                .not_expired_at(parent, epoch)
+                .ok_or(Error::NameExpired)?;
+            if self.env().caller() != parent_owner {
+                ink_env::debug_println!("not the owner of the parent");
+                return Err(Error::NotOwner);
+            }
+            if self.children.get(&name_hash).cloned().unwrap_or_default() > 0 {
+                return Err(Error::HasSubnames);
+            }
+            let _ = self.subnames.take(&name_hash);
+            let _ = self.children.take(&name_hash);
+            let _ = self.names.take(&name_hash);
+            let _ = self.approvals.take(&name_hash);
+            if let Some(count) = self.children.get_mut(&parent) {
+                *count = count.saturating_sub(1);
+            }
+            self.env().emit_event(SubnameDeleted {
+                name: name_hash,
+                parent,
+            });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_owner_from(&self, name: Hash, from: AccountId) -> bool {
+            if let Some((o, _b, _d)) = self.record(name) {
+                from == o
+            } else {
+                false
+            }
         }
 
         #[ink(message, selector = 0xDEADBABE)]
-        pub fn unregister(&mut self, name: Hash) {
-            if self.is_owner(name) {
-                self.unregister_unchecked(name);
+        pub fn unregister(&mut self, name: Hash) -> Result<()> {
+            if self.subnames.contains_key(&name) {
+                ink_env::debug_println!("sub-names are deleted by their parent");
+                Err(Error::SubnameNotAllowed)
+            } else if self.is_owner(name) {
+                self.unregister_unchecked(name)
             } else {
                 ink_env::debug_println!("not the owner");
-                panic!("not the owner");
+                Err(Error::NotOwner)
             }
         }
+
+        /// Moves a name to `new_owner`. The caller must be the owner, the
+        /// account approved for the name or an operator of the owner.
+        #[ink(message)]
+        pub fn transfer(&mut self, name_hash: Hash, new_owner: AccountId) -> Result<()> {
+            let epoch = self.current_epoch()?;
+            self.transfer_at(name_hash, new_owner, epoch)
+        }
+
+        fn transfer_at(&mut self, name_hash: Hash, new_owner: AccountId, epoch: u32) -> Result<()> {
+            let caller = self.env().caller();
+            let (owner, start, duration) = self
+                .not_expired_at(name_hash, epoch)
+                .ok_or(Error::NameExpired)?;
+            if !self.is_approved_or_owner(name_hash, &owner, &caller) {
+                ink_env::debug_println!("not the owner or approved");
+                return Err(Error::NotApproved);
+            }
+            if let Some((parent, _o, start)) = self.subnames.get(&name_hash).cloned() {
+                self.subnames.insert(name_hash, (parent, new_owner, start));
+            } else {
+                self.registry
+                    .insert(name_hash, (new_owner, start, duration));
+            }
+            let _ = self.approvals.take(&name_hash);
+            self.env().emit_event(Transfer {
+                name: name_hash,
+                from: owner,
+                to: new_owner,
+            });
+            Ok(())
+        }
+
+        /// Approves `to` to transfer a single name, only one account can be
+        /// approved at a time and the approval is cleared on transfer.
+        #[ink(message)]
+        pub fn approve(&mut self, name_hash: Hash, to: AccountId) -> Result<()> {
+            let caller = self.env().caller();
+            let owner = if let Some((o, _b, _d)) = self.record(name_hash) {
+                o
+            } else {
+                ink_env::debug_println!("name not found");
+                return Err(Error::NameNotFound);
+            };
+            if caller != owner && !self.is_approved_for_all(owner, caller) {
+                ink_env::debug_println!("not the owner or operator");
+                return Err(Error::NotApproved);
+            }
+            self.approvals.insert(name_hash, to);
+            self.env().emit_event(Approval {
+                name: name_hash,
+                owner,
+                approved: to,
+            });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_approved(&self, name_hash: Hash) -> Option<AccountId> {
+            self.approvals.get(&name_hash).cloned()
+        }
+
+        /// Enables or disables `operator` to manage all names of the caller.
+        #[ink(message)]
+        pub fn set_approval_for_all(&mut self, operator: AccountId, approved: bool) {
+            let caller = self.env().caller();
+            if approved {
+                self.operators.insert((caller, operator), true);
+            } else {
+                let _ = self.operators.take(&(caller, operator));
+            }
+            self.env().emit_event(ApprovalForAll {
+                owner: caller,
+                operator,
+                approved,
+            });
+        }
+
+        #[ink(message)]
+        pub fn is_approved_for_all(&self, owner: AccountId, operator: AccountId) -> bool {
+            self.operators
+                .get(&(owner, operator))
+                .cloned()
+                .unwrap_or(false)
+        }
+
+        fn is_approved_or_owner(
+            &self,
+            name_hash: Hash,
+            owner: &AccountId,
+            from: &AccountId,
+        ) -> bool {
+            *from == *owner
+                || self.approvals.get(&name_hash) == Some(from)
+                || self.is_approved_for_all(*owner, *from)
+        }
     }
 
     /// Unit tests in Rust are normally defined within such a `#[cfg(test)]`
@@ -303,7 +1602,7 @@ mod registry {
         /// We test if the default constructor does its job.
         #[ink::test]
         fn default_works() {
-            let _ = Indexer::default();
+            let _ = new_registry();
             assert!(true);
         }
 
@@ -315,31 +1614,785 @@ mod registry {
             let accounts = default_accounts();
             set_sender(accounts.alice);
             set_balance(contract_id(), contract_balance);
-            let mut myns = Indexer::default();
+            let mut myns = new_registry();
 
             // when
             set_sender(accounts.eve);
             set_balance(accounts.eve, 100);
 
-            assert!(myns.available("myname".to_owned()));
-            let commitment = myns.make_commitment("myname".to_owned(), accounts.eve, 1);
+            assert!(myns.available_at("myname".to_owned(), 0));
+            let commitment = myns
+                .make_commitment("myname".to_owned(), accounts.eve, 1)
+                .unwrap();
             commit(&accounts.eve, &commitment);
-            myns.commit(commitment);
-            assert!(myns.available("myname".to_owned()));
+            assert_eq!(myns.commit(commitment), Ok(()));
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            assert!(myns.available_at("myname".to_owned(), 0));
             register("myname".to_owned(), &accounts.eve, 100, 1);
-            myns.register("myname".to_owned(), accounts.eve, 100, 1);
-            assert!(!myns.available("myname".to_owned()));
+            assert_eq!(
+                myns.register_at(
+                    "myname".to_owned(),
+                    accounts.eve,
+                    100,
+                    &1u32.to_be_bytes(),
+                    0
+                ),
+                Ok(())
+            );
+            assert!(!myns.available_at("myname".to_owned(), 0));
+            // free again once the grace period is over
+            assert!(!myns.available_at("myname".to_owned(), 105));
+            assert!(myns.available_at("myname".to_owned(), 106));
+        }
+
+        /// A commitment is usable between its minimum and maximum age and its
+        /// fee goes back to the committer when it expires unused.
+        #[ink::test]
+        fn commitment_age_and_refund() {
+            let accounts = default_accounts();
+            set_balance(contract_id(), 1_000);
+            set_sender(accounts.alice);
+            let mut myns = new_registry();
+            assert_eq!(myns.set_max_commit_age(2), Ok(()));
+
+            set_balance(accounts.eve, 0);
+            let secret = Hash::from([7; 32]);
+            let commitment = myns
+                .make_commitment_with_secret("myname".to_owned(), accounts.eve, secret)
+                .unwrap();
+            assert_ne!(
+                Ok(commitment),
+                myns.make_commitment("myname".to_owned(), accounts.eve, 7)
+            );
+            commit(&accounts.eve, &commitment);
+            assert_eq!(myns.commit(commitment), Ok(()));
+            assert_eq!(myns.commit_register(&commitment), Err(Error::CommitTooNew));
+            assert_eq!(
+                myns.reclaim_commit(commitment),
+                Err(Error::CommitStillValid)
+            );
+
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            assert_eq!(myns.commit_register(&commitment), Ok(()));
+
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            ink_env::test::advance_block::<ink_env::DefaultEnvironment>();
+            assert_eq!(myns.commit_register(&commitment), Err(Error::CommitExpired));
+            set_sender(accounts.bob);
+            assert_eq!(myns.reclaim_commit(commitment), Ok(()));
+            assert_eq!(get_balance(accounts.eve), 100);
+            assert_eq!(myns.reclaim_commit(commitment), Err(Error::NoCommit));
+        }
+
+        /// Renewing at the last epoch of a name extends its expiry.
+        #[ink::test]
+        fn renew_extends_duration() {
+            let accounts = default_accounts();
+            let mut myns = new_registry();
+            let name_hash = insert_name(&mut myns, "myname", accounts.alice, 0, 10);
+            assert!(myns.not_expired_at(name_hash, 10).is_some());
+            assert!(myns.not_expired_at(name_hash, 11).is_none());
+
+            renew(&accounts.alice, 30_000);
+            assert_eq!(myns.renew_at(name_hash, 5, 10), Ok(()));
+            assert_eq!(
+                myns.not_expired_at(name_hash, 15),
+                Some((accounts.alice, 0, 15))
+            );
+            assert_eq!(myns.status_at(name_hash, 15), NameStatus::Active);
+            assert_eq!(myns.status_at(name_hash, 16), NameStatus::Grace);
+        }
+
+        /// Anyone may pay for a renewal, the name stays with its owner.
+        #[ink::test]
+        fn renew_by_other_payer_keeps_owner() {
+            let accounts = default_accounts();
+            let mut myns = new_registry();
+            let name_hash = insert_name(&mut myns, "myname", accounts.alice, 0, 10);
+
+            renew(&accounts.eve, 60_000);
+            assert_eq!(myns.renew_at(name_hash, 10, 3), Ok(()));
+            assert_eq!(
+                myns.not_expired_at(name_hash, 20),
+                Some((accounts.alice, 0, 20))
+            );
+        }
+
+        #[ink::test]
+        fn renew_after_expiry_fails() {
+            let accounts = default_accounts();
+            let mut myns = new_registry();
+            let name_hash = insert_name(&mut myns, "myname", accounts.alice, 0, 10);
+
+            renew(&accounts.alice, 30_000);
+            assert_eq!(myns.renew_at(name_hash, 5, 16), Err(Error::NameExpired));
+        }
+
+        #[ink::test]
+        fn status_follows_grace_period() {
+            let accounts = default_accounts();
+            let mut myns = new_registry();
+            let unknown = myns.get_hash("unknown".to_owned()).unwrap();
+            let name_hash = insert_name(&mut myns, "myname", accounts.alice, 0, 10);
+            assert_eq!(myns.status_at(unknown, 10), NameStatus::Never);
+            assert_eq!(myns.status_at(name_hash, 10), NameStatus::Active);
+            assert_eq!(myns.status_at(name_hash, 11), NameStatus::Grace);
+            assert_eq!(myns.status_at(name_hash, 15), NameStatus::Grace);
+            assert_eq!(myns.status_at(name_hash, 16), NameStatus::Expired);
+        }
+
+        /// The owner can still renew a name in its grace period.
+        #[ink::test]
+        fn owner_renews_during_grace_period() {
+            let accounts = default_accounts();
+            let mut myns = new_registry();
+            let name_hash = insert_name(&mut myns, "myname", accounts.alice, 0, 10);
+
+            renew(&accounts.alice, 30_000);
+            assert_eq!(myns.renew_at(name_hash, 5, 15), Ok(()));
+            assert_eq!(myns.status_at(name_hash, 15), NameStatus::Active);
+            assert_eq!(
+                myns.not_expired_at(name_hash, 15),
+                Some((accounts.alice, 0, 15))
+            );
+        }
+
+        #[ink::test]
+        fn other_payer_cannot_renew_during_grace_period() {
+            let accounts = default_accounts();
+            let mut myns = new_registry();
+            let name_hash = insert_name(&mut myns, "myname", accounts.alice, 0, 10);
+
+            renew(&accounts.eve, 30_000);
+            assert_eq!(
+                myns.renew_at(name_hash, 5, 11),
+                Err(Error::GracePeriodOwnerOnly)
+            );
+        }
+
+        #[ink::test]
+        fn grace_period_starts_once() {
+            let accounts = default_accounts();
+            let mut myns = new_registry();
+            let name_hash = insert_name(&mut myns, "myname", accounts.alice, 0, 10);
+
+            assert_eq!(
+                myns.start_grace_period_at(name_hash, 10),
+                Err(Error::NotInGracePeriod)
+            );
+            assert_eq!(myns.start_grace_period_at(name_hash, 11), Ok(()));
+            assert_eq!(ink_env::test::recorded_events().count(), 1);
+            assert_eq!(
+                myns.start_grace_period_at(name_hash, 12),
+                Err(Error::GracePeriodAlreadyStarted)
+            );
+        }
+
+        #[ink::test]
+        fn renew_requires_rent_price() {
+            let accounts = default_accounts();
+            let mut myns = new_registry();
+            let name_hash = insert_name(&mut myns, "myname", accounts.alice, 0, 10);
+
+            renew(&accounts.alice, 29_999);
+            assert_eq!(
+                myns.renew_at(name_hash, 5, 10),
+                Err(Error::InsufficientPayment)
+            );
+        }
+
+        /// A transferred name is owned by the new account and the old owner
+        /// loses control over it.
+        #[ink::test]
+        fn transfer_works() {
+            let accounts = default_accounts();
+            let mut myns = new_registry();
+            let name_hash = insert_name(&mut myns, "myname", accounts.alice, 0, 10);
+
+            set_sender(accounts.alice);
+            assert_eq!(myns.transfer_at(name_hash, accounts.bob, 5), Ok(()));
+            assert!(myns.is_owner_from(name_hash, accounts.bob));
+            assert!(!myns.is_owner_from(name_hash, accounts.alice));
+            assert_eq!(
+                myns.not_expired_at(name_hash, 10),
+                Some((accounts.bob, 0, 10))
+            );
+        }
+
+        #[ink::test]
+        fn approved_account_can_transfer_once() {
+            let accounts = default_accounts();
+            let mut myns = new_registry();
+            let name_hash = insert_name(&mut myns, "myname", accounts.alice, 0, 10);
+
+            set_sender(accounts.alice);
+            assert_eq!(myns.approve(name_hash, accounts.charlie), Ok(()));
+            assert_eq!(myns.get_approved(name_hash), Some(accounts.charlie));
+
+            set_sender(accounts.charlie);
+            assert_eq!(myns.transfer_at(name_hash, accounts.bob, 5), Ok(()));
+            assert!(myns.is_owner_from(name_hash, accounts.bob));
+            assert_eq!(myns.get_approved(name_hash), None);
+        }
+
+        #[ink::test]
+        fn operator_can_transfer_all_names() {
+            let accounts = default_accounts();
+            let mut myns = new_registry();
+            let first = insert_name(&mut myns, "first", accounts.alice, 0, 10);
+            let second = insert_name(&mut myns, "second", accounts.alice, 0, 10);
+
+            set_sender(accounts.alice);
+            myns.set_approval_for_all(accounts.charlie, true);
+            assert!(myns.is_approved_for_all(accounts.alice, accounts.charlie));
+
+            set_sender(accounts.charlie);
+            assert_eq!(myns.transfer_at(first, accounts.bob, 5), Ok(()));
+            assert_eq!(myns.transfer_at(second, accounts.bob, 5), Ok(()));
+            assert!(myns.is_owner_from(first, accounts.bob));
+            assert!(myns.is_owner_from(second, accounts.bob));
+        }
+
+        #[ink::test]
+        fn transfer_by_stranger_fails() {
+            let accounts = default_accounts();
+            let mut myns = new_registry();
+            let name_hash = insert_name(&mut myns, "myname", accounts.alice, 0, 10);
+
+            set_sender(accounts.alice);
+            myns.set_approval_for_all(accounts.charlie, true);
+            myns.set_approval_for_all(accounts.charlie, false);
+
+            set_sender(accounts.charlie);
+            assert_eq!(
+                myns.transfer_at(name_hash, accounts.charlie, 5),
+                Err(Error::NotApproved)
+            );
+        }
+
+        /// Only the admin can change the config and every change is
+        /// reported with the old and new values.
+        #[ink::test]
+        fn config_is_admin_only() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut myns = new_registry();
+            assert_eq!(myns.rent_price("myname".to_owned(), 10), 60_000);
+
+            set_sender(accounts.eve);
+            assert_eq!(myns.set_rent_price(1), Err(Error::NotAdmin));
+            assert_eq!(ink_env::test::recorded_events().count(), 0);
+
+            set_sender(accounts.alice);
+            assert_eq!(myns.set_rent_price(1), Ok(()));
+            assert_eq!(myns.set_max_name_length(4), Ok(()));
+            assert_eq!(ink_env::test::recorded_events().count(), 2);
+            assert_eq!(myns.get_config().rent_price, 1);
+            assert_eq!(myns.rent_price("myname".to_owned(), 10), 60);
+            assert!(!myns.valid("myname".to_owned()));
+        }
+
+        /// The highest bidder wins and pays the second highest bid, every
+        /// other bidder is refunded.
+        #[ink::test]
+        fn auction_winner_pays_second_price() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut myns = new_registry();
+            assert_eq!(myns.set_auction_below_length(3), Ok(()));
+            assert_eq!(myns.set_auction_periods(2, 2, 10), Ok(()));
+            set_balance(contract_id(), 0);
+            set_balance(accounts.bob, 0);
+            set_balance(accounts.charlie, 0);
+            set_balance(accounts.eve, 0);
+
+            assert_eq!(myns.start_auction_at("ab".to_owned(), 0), Ok(()));
+            let bids = [
+                (accounts.bob, 100_000, 150_000),
+                (accounts.charlie, 80_000, 80_010),
+                (accounts.eve, 50_000, 60_000),
+            ];
+            let name_hash = myns.get_hash("ab".to_owned()).unwrap();
+            for (bidder, value, deposit) in bids.iter() {
+                let sealed = myns.make_bid("ab".to_owned(), *bidder, *value, 7).unwrap();
+                pay(bidder, *deposit);
+                set_balance(contract_id(), get_balance(contract_id()) + deposit);
+                assert_eq!(myns.bid_at(name_hash, sealed, 1), Ok(()));
+            }
+
+            set_sender(accounts.charlie);
+            assert_eq!(
+                myns.reveal_bid_at("ab".to_owned(), 80_000, 7, 1),
+                Err(Error::NotInRevealPeriod)
+            );
+            for (bidder, value, _) in bids.iter() {
+                set_sender(*bidder);
+                assert_eq!(myns.reveal_bid_at("ab".to_owned(), *value, 7, 2), Ok(()));
+            }
+            assert_eq!(get_balance(accounts.charlie), 80_010);
+            assert_eq!(get_balance(accounts.eve), 60_000);
+
+            assert_eq!(
+                myns.finalize_auction_at("ab".to_owned(), 3),
+                Err(Error::AuctionNotEnded)
+            );
+            // raising the locked deposit after reveal does not change the refund
+            set_sender(accounts.alice);
+            assert_eq!(myns.set_locked_deposit(100_000), Ok(()));
+            assert_eq!(myns.finalize_auction_at("ab".to_owned(), 4), Ok(()));
+            assert_eq!(
+                myns.not_expired_at(name_hash, 4),
+                Some((accounts.bob, 4, 10))
+            );
+            assert_eq!(get_balance(accounts.bob), 150_000 - 80_000 - 10);
+            assert_eq!(get_balance(contract_id()), 80_000 + 10);
+            assert_eq!(myns.get_auction(name_hash), None);
+        }
+
+        /// Bids are placed during the bidding window of a running auction and
+        /// only revealed in that auction, with enough deposit.
+        #[ink::test]
+        fn bids_are_bound_to_their_auction() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut myns = new_registry();
+            assert_eq!(myns.set_auction_below_length(3), Ok(()));
+            set_balance(contract_id(), 0);
+            set_balance(accounts.bob, 0);
+            let name_hash = myns.get_hash("ab".to_owned()).unwrap();
+            assert_eq!(myns.start_auction_at("ab".to_owned(), 5), Ok(()));
+
+            let sealed = myns
+                .make_bid("ab".to_owned(), accounts.bob, 500, 7)
+                .unwrap();
+            pay(&accounts.bob, 10);
+            assert_eq!(
+                myns.bid_at(name_hash, sealed, 5),
+                Err(Error::InsufficientPayment)
+            );
+            pay(&accounts.bob, 505);
+            assert_eq!(
+                myns.bid_at(name_hash, sealed, 4),
+                Err(Error::NotInBiddingPeriod)
+            );
+            assert_eq!(
+                myns.bid_at(name_hash, sealed, 7),
+                Err(Error::NotInBiddingPeriod)
+            );
+            assert_eq!(myns.bid_at(name_hash, sealed, 6), Ok(()));
+
+            // 500 plus the locked deposit of 10 exceeds 505
+            set_sender(accounts.bob);
+            assert_eq!(
+                myns.reveal_bid_at("ab".to_owned(), 500, 7, 7),
+                Err(Error::InsufficientPayment)
+            );
+
+            // a bid of an earlier auction cannot be revealed in a later one
+            let mut auction = myns.get_auction(name_hash).unwrap();
+            auction.start = 20;
+            myns.auctions.insert(name_hash, auction);
+            assert_eq!(
+                myns.reveal_bid_at("ab".to_owned(), 500, 7, 22),
+                Err(Error::BidNotFound)
+            );
+        }
+
+        /// Finalizing refunds the highest bidder once the name was registered
+        /// meanwhile or no longer requires an auction.
+        #[ink::test]
+        fn finalize_refunds_unavailable_names() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut myns = new_registry();
+            assert_eq!(myns.set_auction_below_length(3), Ok(()));
+            assert_eq!(myns.set_auction_periods(2, 2, 10), Ok(()));
+            set_balance(contract_id(), 0);
+            set_balance(accounts.bob, 0);
+
+            for name in ["ab", "cd"].iter() {
+                let name_hash = myns.get_hash((*name).to_owned()).unwrap();
+                assert_eq!(myns.start_auction_at((*name).to_owned(), 0), Ok(()));
+                let sealed = myns
+                    .make_bid((*name).to_owned(), accounts.bob, 50_000, 7)
+                    .unwrap();
+                pay(&accounts.bob, 60_000);
+                set_balance(contract_id(), get_balance(contract_id()) + 60_000);
+                assert_eq!(myns.bid_at(name_hash, sealed, 0), Ok(()));
+                set_sender(accounts.bob);
+                assert_eq!(myns.reveal_bid_at((*name).to_owned(), 50_000, 7, 2), Ok(()));
+            }
+
+            // ab got registered directly, cd no longer requires an auction
+            set_sender(accounts.alice);
+            let ab = insert_name(&mut myns, "ab", accounts.eve, 1, 10);
+            assert_eq!(myns.set_auction_below_length(2), Ok(()));
+            assert_eq!(myns.finalize_auction_at("ab".to_owned(), 4), Ok(()));
+            assert_eq!(myns.finalize_auction_at("cd".to_owned(), 4), Ok(()));
+
+            assert_eq!(myns.not_expired_at(ab, 4), Some((accounts.eve, 1, 10)));
+            let cd = myns.get_hash("cd".to_owned()).unwrap();
+            assert_eq!(myns.not_expired_at(cd, 4), None);
+            assert_eq!(get_balance(accounts.bob), 120_000);
+            assert_eq!(get_balance(contract_id()), 0);
+        }
+
+        /// Names shorter than the auction length cannot be registered.
+        #[ink::test]
+        fn short_names_require_auction() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut myns = new_registry();
+            assert_eq!(myns.set_auction_below_length(3), Ok(()));
+            assert_eq!(
+                myns.start_auction_at("abc".to_owned(), 0),
+                Err(Error::AuctionNotAllowed)
+            );
+            assert_eq!(myns.start_auction_at("ab".to_owned(), 0), Ok(()));
+            assert_eq!(
+                myns.start_auction_at("ab".to_owned(), 1),
+                Err(Error::AuctionInProgress)
+            );
+        }
+
+        /// A bid that was never revealed is refunded once the auction ended.
+        #[ink::test]
+        fn unrevealed_bid_can_be_cancelled() {
+            let accounts = default_accounts();
+            set_sender(accounts.alice);
+            let mut myns = new_registry();
+            assert_eq!(myns.set_auction_below_length(3), Ok(()));
+            set_balance(contract_id(), 1_000);
+            set_balance(accounts.bob, 0);
+
+            let sealed = myns
+                .make_bid("ab".to_owned(), accounts.bob, 500, 7)
+                .unwrap();
+            let name_hash = myns.get_hash("ab".to_owned()).unwrap();
+            pay(&accounts.bob, 1_000);
+            assert_eq!(
+                myns.bid_at(name_hash, sealed, 1),
+                Err(Error::AuctionNotFound)
+            );
+            assert_eq!(myns.start_auction_at("ab".to_owned(), 1), Ok(()));
+            assert_eq!(myns.bid_at(name_hash, sealed, 1), Ok(()));
+            assert_eq!(myns.bid_at(name_hash, sealed, 1), Err(Error::BidExists));
+            assert_eq!(myns.cancel_bid_at(sealed, 4), Err(Error::BidStillLocked));
+
+            set_sender(accounts.eve);
+            assert_eq!(myns.cancel_bid_at(sealed, 5), Err(Error::BidNotFound));
+            set_sender(accounts.bob);
+            assert_eq!(myns.cancel_bid_at(sealed, 5), Ok(()));
+            assert_eq!(get_balance(accounts.bob), 1_000);
+        }
+
+        /// The primary name resolves only while its owner keeps it.
+        #[ink::test]
+        fn primary_name_follows_ownership() {
+            let accounts = default_accounts();
+            let mut myns = new_registry();
+            let name_hash = insert_name(&mut myns, "myname", accounts.alice, 0, 10);
+            assert_eq!(myns.name_of(name_hash), Some("myname".to_owned()));
+            assert_eq!(myns.primary_name_at(accounts.alice, 5), None);
+
+            set_sender(accounts.bob);
+            assert_eq!(myns.set_primary_name_at(name_hash, 5), Err(Error::NotOwner));
+
+            set_sender(accounts.alice);
+            assert_eq!(myns.set_primary_name_at(name_hash, 5), Ok(()));
+            assert_eq!(myns.primary_name_at(accounts.alice, 5), Some(name_hash));
+            assert_eq!(myns.primary_name_at(accounts.alice, 11), None);
+            assert_eq!(
+                myns.set_primary_name_at(name_hash, 11),
+                Err(Error::NameExpired)
+            );
+
+            assert_eq!(myns.transfer_at(name_hash, accounts.bob, 5), Ok(()));
+            assert_eq!(myns.primary_name_at(accounts.alice, 5), None);
+        }
+
+        /// Sub-names are owned separately but expire with their top level name.
+        #[ink::test]
+        fn subname_follows_parent_expiry() {
+            let accounts = default_accounts();
+            let mut myns = new_registry();
+            let acme = insert_name(&mut myns, "acme", accounts.alice, 0, 10);
+            assert_eq!(myns.get_node_hash(vec!["acme".to_owned()]), Ok(acme));
+            assert_eq!(myns.get_hash(" Acme ".to_owned()), Ok(acme));
+
+            set_sender(accounts.alice);
+            let kusama = myns
+                .create_subname_at(acme, "kusama".to_owned(), accounts.bob, 5)
+                .unwrap();
+            assert_eq!(
+                myns.get_node_hash(vec!["Kusama".to_owned(), "acme".to_owned()]),
+                Ok(kusama)
+            );
+            assert_eq!(
+                myns.get_node_hash(vec!["ku sama".to_owned(), "acme".to_owned()]),
+                Err(Error::InvalidName(NameError::InvalidCharacter))
+            );
+            assert_eq!(
+                myns.get_node_hash(Vec::new()),
+                Err(Error::InvalidName(NameError::Empty))
+            );
+            assert_eq!(myns.name_of(kusama), Some("kusama.acme".to_owned()));
+            assert_eq!(
+                myns.create_subname_at(acme, "kusama".to_owned(), accounts.alice, 5),
+                Err(Error::NameNotAvailable)
+            );
+            assert!(myns.is_owner_from(kusama, accounts.bob));
+            assert_eq!(myns.not_expired_at(kusama, 10), Some((accounts.bob, 0, 10)));
+            assert_eq!(myns.status_at(kusama, 12), NameStatus::Grace);
+            assert_eq!(myns.not_expired_at(kusama, 11), None);
+
+            set_sender(accounts.bob);
+            assert_eq!(myns.renew_at(kusama, 10, 5), Err(Error::SubnameNotAllowed));
+            assert_eq!(myns.transfer_at(kusama, accounts.charlie, 5), Ok(()));
+            assert!(myns.is_owner_from(kusama, accounts.charlie));
+            assert!(myns.is_owner_from(acme, accounts.alice));
+
+            insert_name(&mut myns, "acme", accounts.bob, 20, 10);
+            assert_eq!(myns.not_expired_at(kusama, 20), None);
+            assert!(!myns.is_owner_from(kusama, accounts.charlie));
+        }
+
+        /// Only the parent owner creates and deletes sub-names, leaves first.
+        #[ink::test]
+        fn parent_owner_manages_subnames() {
+            let accounts = default_accounts();
+            let mut myns = new_registry();
+            let acme = insert_name(&mut myns, "acme", accounts.alice, 0, 10);
+
+            set_sender(accounts.bob);
+            assert_eq!(
+                myns.create_subname_at(acme, "kusama".to_owned(), accounts.bob, 5),
+                Err(Error::NotOwner)
+            );
+
+            set_sender(accounts.alice);
+            assert_eq!(
+                myns.create_subname_at(acme, "a.b".to_owned(), accounts.bob, 5),
+                Err(Error::InvalidName(NameError::InvalidCharacter))
+            );
+            let kusama = myns
+                .create_subname_at(acme, "kusama".to_owned(), accounts.bob, 5)
+                .unwrap();
+
+            set_sender(accounts.bob);
+            let node = myns
+                .create_subname_at(kusama, "node1".to_owned(), accounts.bob, 5)
+                .unwrap();
+            assert_eq!(
+                myns.get_node_hash(vec![
+                    "node1".to_owned(),
+                    "kusama".to_owned(),
+                    "acme".to_owned()
+                ]),
+                Ok(node)
+            );
+            assert_eq!(myns.delete_subname_at(kusama, 5), Err(Error::NotOwner));
+
+            set_sender(accounts.alice);
+            assert_eq!(myns.delete_subname_at(kusama, 5), Err(Error::HasSubnames));
+            assert_eq!(myns.unregister(kusama), Err(Error::SubnameNotAllowed));
+
+            set_sender(accounts.bob);
+            assert_eq!(myns.delete_subname_at(node, 5), Ok(()));
+            assert_eq!(myns.status_at(node, 5), NameStatus::Never);
+
+            set_sender(accounts.alice);
+            assert_eq!(myns.delete_subname_at(kusama, 5), Ok(()));
+            assert_eq!(myns.not_expired_at(kusama, 5), None);
+        }
+
+        /// Unregistering must only drop the commitment of that name.
+        #[ink::test]
+        fn unregister_after_multiple_registrations() {
+            let accounts = default_accounts();
+            set_balance(contract_id(), 1_000);
+            let mut myns = new_registry();
+            let mut names = Vec::new();
+            for (i, name) in ["first", "second", "third"].iter().enumerate() {
+                let commitment = myns
+                    .make_commitment((*name).to_owned(), accounts.alice, 1)
+                    .unwrap();
+                let name_hash = myns.get_hash((*name).to_owned()).unwrap();
+                assert_eq!(
+                    myns.register_unchecked(
+                        name_hash,
+                        (*name).to_owned(),
+                        accounts.alice,
+                        i as u32,
+                        10,
+                        10
+                    ),
+                    Ok(())
+                );
+                myns.link_commit(commitment, name_hash);
+                names.push((commitment, name_hash));
+            }
+
+            set_sender(accounts.alice);
+            assert_eq!(myns.unregister(names[2].1), Ok(()));
+            assert_eq!(myns.unregister(names[0].1), Ok(()));
+            assert_eq!(myns.commit_name.get(&names[0].0), None);
+            assert_eq!(myns.commit_name.get(&names[1].0), Some(&names[1].1));
+            assert_eq!(myns.commit_name.get(&names[2].0), None);
+            assert_eq!(myns.name_commit.get(&names[1].1), Some(&names[1].0));
+            assert_eq!(myns.name_commit.get(&names[2].1), None);
+            assert_eq!(myns.unregister(names[1].1), Ok(()));
+            assert_eq!(myns.commit_name.len(), 0);
+            assert_eq!(myns.name_commit.len(), 0);
+        }
+
+        /// Registering a name again replaces its old commitment.
+        #[ink::test]
+        fn reregister_replaces_commitment() {
+            let accounts = default_accounts();
+            set_balance(contract_id(), 1_000);
+            let mut myns = new_registry();
+            let name_hash = insert_name(&mut myns, "myname", accounts.alice, 0, 10);
+            let old = myns
+                .make_commitment("myname".to_owned(), accounts.alice, 1)
+                .unwrap();
+            myns.link_commit(old, name_hash);
+
+            assert_eq!(
+                myns.register_unchecked(name_hash, "myname".to_owned(), accounts.bob, 20, 10, 10),
+                Ok(())
+            );
+            let new = myns
+                .make_commitment("myname".to_owned(), accounts.bob, 2)
+                .unwrap();
+            myns.link_commit(new, name_hash);
+            assert_eq!(myns.commit_name.get(&old), None);
+            assert_eq!(myns.commit_name.get(&new), Some(&name_hash));
+
+            set_sender(accounts.bob);
+            assert_eq!(myns.unregister(name_hash), Ok(()));
+            assert_eq!(myns.commit_name.len(), 0);
+            assert_eq!(myns.name_commit.len(), 0);
+        }
+
+        /// Names are compared in their normalized form.
+        #[ink::test]
+        fn validate_normalizes_names() {
+            let mut myns = new_registry();
+            assert_eq!(myns.validate(" Acme ".to_owned()), Ok("acme".to_owned()));
+            assert_eq!(
+                myns.validate("Cafe\u{301}".to_owned()),
+                Ok("caf\u{e9}".to_owned())
+            );
+            assert_eq!(
+                myns.make_commitment("ACME".to_owned(), default_accounts().alice, 1),
+                myns.make_commitment("acme".to_owned(), default_accounts().alice, 1)
+            );
+            assert_eq!(myns.validate("".to_owned()), Err(NameError::TooShort));
+            assert_eq!(myns.validate("   ".to_owned()), Err(NameError::TooShort));
+            assert_eq!(
+                myns.validate("ac me".to_owned()),
+                Err(NameError::InvalidCharacter)
+            );
+            assert_eq!(
+                myns.validate("ac\u{7}me".to_owned()),
+                Err(NameError::InvalidCharacter)
+            );
+            assert_eq!(myns.validate("a".repeat(257)), Err(NameError::TooLong));
+
+            set_sender(default_accounts().alice);
+            assert_eq!(myns.set_ascii_only(true), Ok(()));
+            assert_eq!(
+                myns.validate("caf\u{e9}".to_owned()),
+                Err(NameError::NotAscii)
+            );
+            assert_eq!(myns.set_min_name_length(3), Ok(()));
+            assert_eq!(myns.validate("ab".to_owned()), Err(NameError::TooShort));
+        }
+
+        /// Only the admin manages reserved names and they cannot be taken.
+        #[ink::test]
+        fn reserved_names_are_not_available() {
+            let accounts = default_accounts();
+            let mut myns = new_registry();
+
+            set_sender(accounts.bob);
+            assert_eq!(
+                myns.set_reserved("polkadot".to_owned(), true),
+                Err(Error::NotAdmin)
+            );
+
+            set_sender(accounts.alice);
+            assert_eq!(myns.set_reserved("Polkadot".to_owned(), true), Ok(()));
+            assert!(myns.is_reserved("polkadot".to_owned()));
+            assert!(!myns.available_at("POLKADOT ".to_owned(), 0));
+            assert_eq!(
+                myns.make_commitment("polkadot".to_owned(), accounts.bob, 1),
+                Err(Error::InvalidName(NameError::Reserved))
+            );
+            assert_eq!(myns.set_reserved("polkadot".to_owned(), false), Ok(()));
+            assert_eq!(
+                myns.validate("polkadot".to_owned()),
+                Ok("polkadot".to_owned())
+            );
+        }
+
+        /// Status never wraps around for epochs before the registration.
+        #[ink::test]
+        fn status_at_epoch_boundaries() {
+            let accounts = default_accounts();
+            let mut myns = new_registry();
+            let name_hash = insert_name(&mut myns, "myname", accounts.alice, 5, 10);
+            assert_eq!(myns.status_at(name_hash, 0), NameStatus::Never);
+            assert_eq!(myns.status_at(name_hash, 4), NameStatus::Never);
+            assert_eq!(myns.status_at(name_hash, 5), NameStatus::Active);
+            assert_eq!(myns.status_at(name_hash, 15), NameStatus::Active);
+            assert_eq!(myns.status_at(name_hash, 16), NameStatus::Grace);
+            assert_eq!(myns.status_at(name_hash, 20), NameStatus::Grace);
+            assert_eq!(myns.status_at(name_hash, 21), NameStatus::Expired);
+            assert_eq!(myns.status_at(name_hash, u32::MAX), NameStatus::Expired);
+            assert!(myns.not_expired_at(name_hash, 4).is_none());
+            assert!(myns.not_expired_at(name_hash, 5).is_some());
+            assert!(myns.not_expired_at(name_hash, 16).is_none());
+        }
+
+        fn new_registry() -> Registry {
+            let accounts = default_accounts();
+            Registry::new(
+                ink_env::call::FromAccountId::from_account_id(accounts.django),
+                RegistryConfig {
+                    grace_period: 5,
+                    ..Default::default()
+                },
+            )
+        }
+
+        fn insert_name(
+            registry: &mut Registry,
+            name: &str,
+            owner: AccountId,
+            on: u32,
+            duration: u32,
+        ) -> Hash {
+            let name_hash = registry.get_hash(name.to_owned()).unwrap();
+            registry.registry.insert(name_hash, (owner, on, duration));
+            registry.names.insert(name_hash, name.to_owned());
+            name_hash
+        }
+
+        fn contract_id() -> AccountId {
+            ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into())
         }
 
         fn set_sender(sender: AccountId) {
             let callee =
                 ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into());
-            test::push_execution_context::<Environment>(
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
                 sender,
                 callee,
                 1000000,
                 1000000,
-                test::CallData::new(call::Selector::new([0x00; 4])), // dummy
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])), // dummy
             );
         }
 
@@ -348,6 +2401,21 @@ mod registry {
                 .expect("Off-chain environment should have been initialized already")
         }
 
+        fn get_balance(account_id: AccountId) -> Balance {
+            ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(account_id)
+                .expect("Cannot get account balance")
+        }
+
+        fn pay(from: &AccountId, mock_transferred_balance: Balance) {
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                *from,
+                contract_id(),
+                1000000,
+                mock_transferred_balance,
+                ink_env::test::CallData::new(ink_env::call::Selector::new([0x00; 4])), // dummy
+            );
+        }
+
         fn set_balance(account_id: AccountId, balance: Balance) {
             ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(account_id, balance)
                 .expect("Cannot set account balance");
@@ -373,6 +2441,20 @@ mod registry {
             );
         }
 
+        fn renew(from: &AccountId, mock_transferred_balance: Balance) {
+            // 0xBEEFCAFE
+            let data = ink_env::test::CallData::new(ink_env::call::Selector::new([
+                0xBE, 0xEF, 0xCA, 0xFE,
+            ]));
+            ink_env::test::push_execution_context::<ink_env::DefaultEnvironment>(
+                *from,
+                contract_id(),
+                1000000,
+                mock_transferred_balance,
+                data,
+            );
+        }
+
         fn register(name: String, from: &AccountId, duration: u32, secret: u32) {
             // 0xCAFEBABE
             let mut data = ink_env::test::CallData::new(ink_env::call::Selector::new([
@@ -2,16 +2,21 @@
 
 use ink_lang as ink;
 
-pub use self::subscription::{SubscriberData, Subscription};
+pub use self::subscription::{
+    Error, Plan, Receipt, ReceiptStatus, Result, SubscriberData, Subscription, CHALLENGE_WINDOW,
+    GET_CID, GET_VERDICT, MAX_KEYS, RESOLUTION_WINDOW,
+};
 
 #[ink::contract]
 mod subscription {
-    use epoch_proxy::EpochProxy;
+    use directory::{resolve, Directory, DISPUTE, EPOCH, REGISTRY};
+    use epoch::Epoch;
     use ink_env;
+    use ink_env::call::{build_call, ExecutionInput, Selector};
     use ink_lang::EmitEvent;
-    use ink_prelude::collections::BTreeMap;
+    use ink_prelude::vec::Vec;
     use ink_storage::collections::HashMap as StorageHashMap;
-    use registry_proxy::RegistryProxy;
+    use registry::Registry;
 
     #[cfg_attr(
         feature = "std",
@@ -30,18 +35,220 @@ mod subscription {
     )]
     pub struct SubscriberData {
         pub balance: Balance,
+        /// Epoch usage accrues from, moved forward by every settlement.
         pub on: BlockNumber,
+        /// Epoch the subscription started.
+        pub since: BlockNumber,
+        /// Index of the plan in `get_plans` of the name.
+        pub plan: u32,
+        /// Price per epoch of the plan when the subscription started.
+        pub rate: Balance,
+        /// Balance ran out at the last settlement, set again by a top-up.
+        pub lapsed: bool,
+        /// Public keys allowed to use the subscription off-chain.
+        pub keys: Vec<[u8; 32]>,
+        /// Charged through receipts instead of per epoch.
+        pub metered: bool,
     }
 
+    /// Pricing published by the owner of a name.
+    #[cfg_attr(
+        feature = "std",
+        derive(::scale_info::TypeInfo, ::ink_storage::traits::StorageLayout,)
+    )]
+    #[derive(
+        Default,
+        Debug,
+        PartialEq,
+        Eq,
+        Clone,
+        scale::Encode,
+        scale::Decode,
+        ::ink_storage::traits::SpreadLayout,
+        ::ink_storage::traits::PackedLayout,
+    )]
+    pub struct Plan {
+        /// Price per epoch, zero for a free tier.
+        pub rate: Balance,
+        /// Epochs a new subscription must pay upfront.
+        pub min_epochs: u32,
+        /// Maximum number of subscribers, zero for no limit.
+        pub max_subscribers: u32,
+        /// Current number of subscribers.
+        pub subscribers: u32,
+        /// Closed plans keep their subscribers but accept no new ones.
+        pub open: bool,
+        /// `rate` is the price per unit reported in receipts, nothing
+        /// accrues per epoch.
+        pub metered: bool,
+    }
+
+    /// State of a usage receipt.
+    #[cfg_attr(
+        feature = "std",
+        derive(::scale_info::TypeInfo, ::ink_storage::traits::StorageLayout,)
+    )]
+    #[derive(
+        Debug,
+        PartialEq,
+        Eq,
+        Clone,
+        Copy,
+        scale::Encode,
+        scale::Decode,
+        ::ink_storage::traits::SpreadLayout,
+        ::ink_storage::traits::PackedLayout,
+    )]
+    pub enum ReceiptStatus {
+        /// Can be accepted or challenged by the subscriber.
+        Pending,
+        /// Challenged with the cid of a dispute raised on `Dispute`.
+        Challenged(Hash),
+        /// Deducted from the subscriber balance.
+        Charged,
+        /// Dropped after the dispute was upheld, or when the subscription
+        /// ended before it was settled.
+        Rejected,
+    }
+
+    /// Usage reported by the owner of a name for one metered subscriber.
+    #[cfg_attr(
+        feature = "std",
+        derive(::scale_info::TypeInfo, ::ink_storage::traits::StorageLayout,)
+    )]
+    #[derive(
+        Debug,
+        PartialEq,
+        Eq,
+        Clone,
+        scale::Encode,
+        scale::Decode,
+        ::ink_storage::traits::SpreadLayout,
+        ::ink_storage::traits::PackedLayout,
+    )]
+    pub struct Receipt {
+        pub subscriber: AccountId,
+        /// Owner of the name who submitted the receipt and gets paid for it.
+        pub owner: AccountId,
+        /// Epoch the usage happened in.
+        pub epoch: u32,
+        /// Queries or bytes served, priced at the subscriber rate.
+        pub units: u64,
+        pub amount: Balance,
+        /// Epoch the receipt was submitted in.
+        pub submitted: u32,
+        pub status: ReceiptStatus,
+    }
+
+    /// Epochs a subscriber has to challenge a receipt before anyone can
+    /// charge it.
+    pub const CHALLENGE_WINDOW: u32 = 2;
+
+    /// Epochs after the challenge window the dispute on a challenged
+    /// receipt has to reach a verdict, the receipt is charged afterwards.
+    /// Covers the whole lifetime of a dispute on `Dispute`.
+    pub const RESOLUTION_WINDOW: u32 = 10;
+
+    /// Selector of the `get_verdict(hash_name: Hash, subscriber: AccountId,
+    /// cid: Hash) -> Option<bool>` message of `Dispute`, called without the
+    /// crate since it depends on this one.
+    pub const GET_VERDICT: [u8; 4] = [0xDE, 0xC1, 0xDE, 0x00];
+
+    /// Selector of the `get_cid(hash_name: Hash, subscriber: AccountId) ->
+    /// Option<(Hash, u32)>` message of `Dispute`.
+    pub const GET_CID: [u8; 4] = [0xDE, 0xC1, 0xDE, 0x01];
+
+    /// Errors that can occur upon calling this contract.
+    #[cfg_attr(feature = "std", derive(::scale_info::TypeInfo))]
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, scale::Encode, scale::Decode)]
+    pub enum Error {
+        /// Caller is not the owner of the name.
+        NotOwner,
+        /// Name is not registered, expired or has no subscribers.
+        NameNotFound,
+        /// Account is not subscribed to the name.
+        SubscriberNotFound,
+        /// Minimum subscription period was not reached yet.
+        MinThresholdNotMet,
+        /// Transferred balance does not cover the minimum subscription.
+        InsufficientPayment,
+        /// Epoch schedule did not start yet.
+        BeforeGenesis,
+        /// Transfer would have brought the contract below subsistence threshold.
+        BelowSubsistenceThreshold,
+        /// Transfer of funds failed.
+        TransferFailed,
+        /// Name has no plan with the given id.
+        PlanNotFound,
+        /// Plan is closed or reached its maximum number of subscribers.
+        PlanUnavailable,
+        /// Subscriber is already on a different plan.
+        PlanMismatch,
+        /// Name is still registered, subscribers unsubscribe instead.
+        NameActive,
+        /// Key is already registered for the name.
+        KeyExists,
+        /// Key is not registered by the caller.
+        KeyNotFound,
+        /// Subscriber reached `MAX_KEYS`.
+        TooManyKeys,
+        /// Subscription is not metered.
+        NotMetered,
+        /// Name has no receipt with the given id.
+        ReceiptNotFound,
+        /// Receipt is not in the state required by the call.
+        InvalidReceiptStatus,
+        /// Receipt can no longer be challenged.
+        ChallengeWindowClosed,
+        /// Receipt can still be challenged.
+        ChallengeWindowOpen,
+        /// Dispute on the receipt has no verdict yet.
+        DisputePending,
+        /// Subscriber has receipts which are not charged or rejected yet.
+        OpenReceipts,
+        /// Subscriber has no dispute raised with the cid.
+        DisputeNotFound,
+        /// Receipt epoch is before the subscription or in the future.
+        InvalidEpoch,
+        /// Subscriber already has a receipt for the epoch.
+        ReceiptExists,
+        /// Component is not set in the directory.
+        ComponentNotFound,
+        /// Call to the Dispute component failed.
+        DisputeCallFailed,
+    }
+
+    /// Maximum number of keys a subscriber can register for one name.
+    pub const MAX_KEYS: usize = 8;
+
+    /// Type alias for the contract's result type.
+    pub type Result<T> = core::result::Result<T, Error>;
+
     /// Defines the storage of your contract.
     /// Add new fields to the below struct in order
     /// to add new static storage fields to your contract.
     #[ink(storage)]
     pub struct Subscription {
         /// Stores subscription value on the storage.
-        subscription: StorageHashMap<Hash, BTreeMap<AccountId, SubscriberData>>,
-        registry: RegistryProxy,
-        epoch: EpochProxy,
+        subscription: StorageHashMap<(Hash, AccountId), SubscriberData>,
+        /// Subscribers of a name by position, so claims can be paginated.
+        subscribers: StorageHashMap<(Hash, u32), AccountId>,
+        /// Position of each subscriber in `subscribers`.
+        positions: StorageHashMap<(Hash, AccountId), u32>,
+        /// Number of subscribers of each name.
+        counts: StorageHashMap<Hash, u32>,
+        plans: StorageHashMap<Hash, Vec<Plan>>,
+        /// Subscriber owning each registered key of a name.
+        keys: StorageHashMap<(Hash, [u8; 32]), AccountId>,
+        /// Usage receipts of metered subscribers by name and id.
+        receipts: StorageHashMap<(Hash, u32), Receipt>,
+        /// Number of receipts submitted for each name.
+        receipt_count: StorageHashMap<Hash, u32>,
+        /// Ids of the pending or challenged receipts of each subscriber.
+        open_receipts: StorageHashMap<(Hash, AccountId), Vec<u32>>,
+        /// Receipt id of each subscriber and usage epoch.
+        receipt_ids: StorageHashMap<(Hash, AccountId, u32), u32>,
+        directory: Directory,
     }
 
     #[ink(event)]
@@ -54,6 +261,50 @@ mod subscription {
         value: Balance,
     }
 
+    /// Emitted whenever a receipt is submitted or changes status.
+    #[ink(event)]
+    pub struct ReceiptChanged {
+        #[ink(topic)]
+        name: Hash,
+        #[ink(topic)]
+        subscriber: AccountId,
+        id: u32,
+        amount: Balance,
+        status: ReceiptStatus,
+    }
+
+    /// Emitted whenever a subscriber adds, revokes or rotates a key.
+    #[ink(event)]
+    pub struct KeyChanged {
+        #[ink(topic)]
+        name: Hash,
+        #[ink(topic)]
+        from: AccountId,
+        old: Option<[u8; 32]>,
+        new: Option<[u8; 32]>,
+    }
+
+    /// Emitted when a settlement finds the subscriber balance exhausted.
+    #[ink(event)]
+    pub struct SubscriptionLapsed {
+        #[ink(topic)]
+        name: Hash,
+        #[ink(topic)]
+        from: AccountId,
+        /// First epoch not covered by the balance.
+        paid_until: u32,
+    }
+
+    /// Emitted whenever the owner of a name adds or closes a plan.
+    #[ink(event)]
+    pub struct PlanChanged {
+        #[ink(topic)]
+        name: Hash,
+        #[ink(topic)]
+        id: u32,
+        plan: Plan,
+    }
+
     #[ink(event)]
     pub struct Unsubscribe {
         #[ink(topic)]
@@ -64,38 +315,187 @@ mod subscription {
         value: Balance,
     }
 
+    /// Emitted whenever a subscription ends without going through
+    /// `unsubscribe`, `usage` is paid to the owner and `value` refunded.
     #[ink(event)]
-    pub struct Claimed {
+    pub struct Refunded {
+        #[ink(topic)]
+        name: Hash,
+        #[ink(topic)]
+        from: AccountId,
+        usage: Balance,
+        value: Balance,
+    }
+
+    /// Emitted whenever a subscriber adds balance to an existing subscription.
+    #[ink(event)]
+    pub struct ToppedUp {
         #[ink(topic)]
         name: Hash,
         #[ink(topic)]
-        value: BTreeMap<AccountId, BlockNumber>,
+        from: AccountId,
+        value: Balance,
+        settled: Balance,
+        balance: Balance,
+    }
+
+    /// Emitted whenever the owner claims the usage of the subscribers at
+    /// positions `start..end`.
+    #[ink(event)]
+    pub struct Claimed {
         #[ink(topic)]
+        name: Hash,
+        start: u32,
+        end: u32,
         total: Balance,
     }
 
     impl Subscription {
         /// Initializes the value to the initial value.
         #[ink(constructor)]
-        pub fn new(init_value: RegistryProxy, epoch: EpochProxy) -> Self {
+        pub fn new(directory: Directory) -> Self {
             Self {
-                registry: init_value,
-                epoch,
+                directory,
                 subscription: Default::default(),
+                subscribers: Default::default(),
+                positions: Default::default(),
+                counts: Default::default(),
+                plans: Default::default(),
+                keys: Default::default(),
+                receipts: Default::default(),
+                receipt_count: Default::default(),
+                open_receipts: Default::default(),
+                receipt_ids: Default::default(),
+            }
+        }
+
+        fn epoch(&self) -> Result<Epoch> {
+            resolve(&self.directory, EPOCH).ok_or(Error::ComponentNotFound)
+        }
+
+        fn registry(&self) -> Result<Registry> {
+            resolve(&self.directory, REGISTRY).ok_or(Error::ComponentNotFound)
+        }
+
+        #[ink(message)]
+        pub fn get_current_epoch(&self) -> Option<u32> {
+            self.epoch().ok()?.get_current_epoch()
+        }
+
+        fn current_epoch(&self) -> Result<u32> {
+            self.epoch()?
+                .get_current_epoch()
+                .ok_or(Error::BeforeGenesis)
+        }
+
+        fn usage_at(rate: Balance, on: u32, curr: u32) -> Balance {
+            rate.saturating_mul(curr.saturating_sub(on) as Balance)
+        }
+
+        /// Usage of `d` up to `epoch`, capped at its remaining balance.
+        fn accrued_at(d: &SubscriberData, epoch: u32) -> Balance {
+            if d.metered {
+                return 0;
+            }
+            Self::usage_at(d.rate, d.on, epoch).min(d.balance)
+        }
+
+        /// First epoch no longer covered by the balance of `d`, free plans
+        /// never run out and metered ones only once a receipt drains the
+        /// balance.
+        fn paid_until(d: &SubscriberData) -> u32 {
+            if d.rate == 0 || (d.metered && d.balance > 0) {
+                return u32::MAX;
+            }
+            let epochs = d.balance / d.rate;
+            d.on.saturating_add(epochs.min(u32::MAX as Balance) as u32)
+        }
+
+        /// Deducts `usage` and credits `payment` to the subscription of `from`
+        /// to `name`, accrual restarts from `epoch`. Marks the subscription as
+        /// lapsed once a paid plan has no balance left and returns the new
+        /// balance.
+        fn apply_at(
+            &mut self,
+            name: Hash,
+            from: AccountId,
+            usage: Balance,
+            payment: Balance,
+            epoch: u32,
+        ) -> Balance {
+            let mut lapsed = None;
+            let mut balance = 0;
+            if let Some(d) = self.subscription.get_mut(&(name, from)) {
+                let paid_until = Self::paid_until(d);
+                d.balance = (d.balance - usage).saturating_add(payment);
+                d.on = epoch;
+                let lapses = d.balance == 0 && d.rate > 0;
+                if lapses && !d.lapsed {
+                    lapsed = Some(paid_until);
+                }
+                d.lapsed = lapses;
+                balance = d.balance;
+            }
+            if let Some(paid_until) = lapsed {
+                EmitEvent::<Self>::emit_event(
+                    self.env(),
+                    SubscriptionLapsed {
+                        name,
+                        from,
+                        paid_until,
+                    },
+                );
             }
+            balance
+        }
+
+        /// Remaining balance of `subscriber` once the usage accrued up to the
+        /// current epoch is paid.
+        #[ink(message)]
+        pub fn balance_of(&self, name: Hash, subscriber: AccountId) -> Option<Balance> {
+            let epoch = self.get_current_epoch()?;
+            self.balance_of_at(name, subscriber, epoch)
+        }
+
+        fn balance_of_at(&self, name: Hash, subscriber: AccountId, epoch: u32) -> Option<Balance> {
+            self.get_subscription(name, subscriber)
+                .map(|d| d.balance - Self::accrued_at(&d, epoch))
         }
 
-        pub fn get_indexer_rate(&self) -> Balance {
-            self.registry.get().get_indexer_rate()
+        /// First epoch the balance of `subscriber` does not pay for, so the
+        /// subscription is active for every epoch before it. `u32::MAX` on a
+        /// free plan.
+        #[ink(message)]
+        pub fn paid_until_epoch(&self, name: Hash, subscriber: AccountId) -> Option<u32> {
+            self.get_subscription(name, subscriber)
+                .map(|d| Self::paid_until(&d))
         }
 
+        /// Pays the usage of `subscriber` accrued so far to the name owner,
+        /// anyone can call it. Returns the settled amount.
         #[ink(message)]
-        pub fn get_current_epoch(&self) -> u32 {
-            self.epoch.get().get_current_epoch()
+        pub fn settle(&mut self, name: Hash, subscriber: AccountId) -> Result<Balance> {
+            let epoch = self.current_epoch()?;
+            let owner = self.name_owner(name)?;
+            self.settle_at(name, subscriber, epoch, owner)
         }
 
-        fn get_subscription_usage(&self, on: u32, curr: u32) -> Balance {
-            self.get_indexer_rate() * (curr - on) as Balance
+        fn settle_at(
+            &mut self,
+            name: Hash,
+            from: AccountId,
+            epoch: u32,
+            owner: AccountId,
+        ) -> Result<Balance> {
+            let d = self
+                .get_subscription(name, from)
+                .ok_or(Error::SubscriberNotFound)?;
+            let settled = Self::accrued_at(&d, epoch);
+            if settled > 0 {
+                self.transfer_balance(owner, settled)?;
+            }
+            self.apply_at(name, from, settled, 0, epoch);
+            Ok(settled)
         }
 
         fn get_min_subscriber_period(&self) -> BlockNumber {
@@ -104,226 +504,1642 @@ mod subscription {
 
         #[ink(message)]
         pub fn not_expired(&self, hash_name: Hash) -> Option<(AccountId, BlockNumber, u32)> {
-            self.registry.get().not_expired(hash_name.clone())
+            self.registry().ok()?.not_expired(hash_name.clone())
         }
 
         #[ink(message)]
         pub fn is_owner_from(&self, name: Hash, from: AccountId) -> bool {
-            self.registry.get().is_owner_from(name.clone(), from)
+            self.registry()
+                .map_or(false, |registry| registry.is_owner_from(name.clone(), from))
+        }
+
+        fn is_owner(&self, hash_name: Hash) -> Result<bool> {
+            Ok(self
+                .registry()?
+                .is_owner_from(hash_name.clone(), self.env().caller().clone()))
+        }
+
+        /// Owner of `name` while it is not expired.
+        fn name_owner(&self, name: Hash) -> Result<AccountId> {
+            let (owner, _b, _d) = self
+                .registry()?
+                .not_expired(name)
+                .ok_or(Error::NameNotFound)?;
+            Ok(owner)
+        }
+
+        /// Caller when it owns `name` and the name is not expired.
+        fn caller_owner(&self, name: Hash) -> Result<AccountId> {
+            Self::owned_by(self.registry()?.not_expired(name), self.env().caller())
         }
 
-        fn is_owner(&self, hash_name: Hash) -> bool {
-            self.registry
-                .get()
-                .is_owner_from(hash_name.clone(), self.env().caller().clone())
+        /// Checks a `not_expired` record of the registry against `caller`,
+        /// expired names have no record and no owner.
+        fn owned_by(
+            record: Option<(AccountId, BlockNumber, u32)>,
+            caller: AccountId,
+        ) -> Result<AccountId> {
+            let (owner, _b, _d) = record.ok_or(Error::NameNotFound)?;
+            if owner != caller {
+                ink_env::debug_println!("not the owner");
+                return Err(Error::NotOwner);
+            }
+            Ok(owner)
+        }
+
+        fn transfer_balance(&self, to: AccountId, value: Balance) -> Result<()> {
+            self.env().transfer(to, value).map_err(|err| match err {
+                ink_env::Error::BelowSubsistenceThreshold => Error::BelowSubsistenceThreshold,
+                _ => Error::TransferFailed,
+            })
         }
 
+        /// Subscribes `from` to `plan` of `name`, the payment must cover the
+        /// plan minimum epochs. An existing subscriber tops up instead.
         #[ink(message, payable, selector = 0xBABECAFE)]
-        pub fn subscribe(&mut self, name: Hash, from: AccountId) {
+        pub fn subscribe(&mut self, name: Hash, from: AccountId, plan: u32) -> Result<()> {
             let payment = self.env().transferred_balance();
             ink_env::debug_println!("subscribe payment: {}", payment);
-            assert!(
-                self.env().transferred_balance() >= self.get_indexer_rate() * 30,
-                "mininum subscription is 30 blocks"
-            );
             // let on = self.env().block_number();
-            let on = self.get_current_epoch();
-            self.subscription
-                .entry(name.clone())
-                .and_modify(|old_value_map| {
-                    old_value_map
-                        .entry(from.clone())
-                        .and_modify(|ov| {
-                            let _b = (*ov).balance.saturating_add(payment.into());
-                        })
-                        .or_insert(SubscriberData {
-                            balance: payment.into(),
-                            on,
-                        });
-                })
-                .or_insert({
-                    let mut map = BTreeMap::new();
-                    let _ = map.insert(
-                        from,
-                        SubscriberData {
-                            balance: payment.into(),
-                            on,
-                        },
-                    );
-                    map
-                });
+            let on = self.current_epoch()?;
+            if let Some(d) = self.get_subscription(name, from) {
+                if d.plan != plan {
+                    return Err(Error::PlanMismatch);
+                }
+                let owner = self.name_owner(name)?;
+                return self.top_up_at(name, from, payment, on, owner);
+            }
+            self.subscribe_at(name, from, plan, payment, on)
+        }
+
+        fn subscribe_at(
+            &mut self,
+            name: Hash,
+            from: AccountId,
+            plan: u32,
+            payment: Balance,
+            on: u32,
+        ) -> Result<()> {
+            let p = self.get_plan(name, plan).ok_or(Error::PlanNotFound)?;
+            if !p.open || (p.max_subscribers > 0 && p.subscribers >= p.max_subscribers) {
+                ink_env::debug_println!("plan unavailable");
+                return Err(Error::PlanUnavailable);
+            }
+            if payment < p.rate.saturating_mul(p.min_epochs as Balance) {
+                ink_env::debug_println!("mininum subscription is {} epochs", p.min_epochs);
+                return Err(Error::InsufficientPayment);
+            }
+            let data = SubscriberData {
+                balance: payment,
+                on,
+                since: on,
+                plan,
+                rate: p.rate,
+                lapsed: false,
+                keys: Vec::new(),
+                metered: p.metered,
+            };
+            self.insert_subscriber(name, from, data);
+            self.update_subscribers(name, plan, |n| n.saturating_add(1));
             EmitEvent::<Self>::emit_event(
                 self.env(),
                 Subscribe {
                     name,
                     from,
-                    value: payment.into(),
+                    value: payment,
                 },
             );
+            Ok(())
+        }
+
+        fn update_subscribers(&mut self, name: Hash, plan: u32, f: impl FnOnce(u32) -> u32) {
+            if let Some(p) = self
+                .plans
+                .get_mut(&name)
+                .and_then(|plans| plans.get_mut(plan as usize))
+            {
+                p.subscribers = f(p.subscribers);
+            }
         }
 
+        /// Publishes a new plan for `name` and returns its id, only the name
+        /// owner can do it. `subscribers` and `open` of `plan` are ignored.
         #[ink(message)]
-        pub fn unsubscribe(&mut self, name: Hash) {
-            let caller = self.env().caller();
-            // let bn = self.env().block_number();
-            let epoch = self.get_current_epoch();
-            let min = self.get_min_subscriber_period();
-            if let Some(a) = self.subscription.get(&name) {
-                if let Some(d) = a.get(&caller) {
-                    ink_env::debug_println!("epoch/min/on: {:?}/{:?}/{:?}", epoch, min, d.on);
-                    if (epoch - d.on) < min {
-                        ink_env::debug_println!("min threshold not met");
-                        panic!("min threshold not met");
-                    }
-                } else {
-                    ink_env::debug_println!("subscriber not found");
-                    panic!("subscriber not found");
-                }
+        pub fn add_plan(&mut self, name: Hash, plan: Plan) -> Result<u32> {
+            if self.is_owner(name)? {
+                Ok(self.add_plan_unchecked(name, plan))
             } else {
-                ink_env::debug_println!("name not found");
-                panic!("name not found");
-            }
-            let success = {
-                if let Some(a) = self.subscription.get(&name) {
-                    if let Some(d) = a.get(&caller) {
-                        let value = (*d).balance;
-                        let usage = self.get_subscription_usage((*d).on, epoch);
-                        let ret = value - usage;
-                        if ret > 0 {
-                            match self.env().transfer(caller.clone(), ret) {
-                                Err(ink_env::Error::BelowSubsistenceThreshold) => {
-                                    panic!(
-                                        "requested transfer would have brought contract\
-                                        below subsistence threshold!"
-                                    )
-                                }
-                                Err(_) => panic!("transfer failed!"),
-                                Ok(_) => {
-                                    if let Some((ref o, _b, _d)) = self.not_expired(name.clone()) {
-                                        match self.env().transfer(o.clone(), usage) {
-                                            Err(ink_env::Error::BelowSubsistenceThreshold) => {
-                                                panic!(
-                                                    "requested transfer would have brought contract\
-                                                    below subsistence threshold!"
-                                                )
-                                            }
-                                            Err(_) => panic!("transfer failed!"),
-                                            Ok(_) => Some(ret),
-                                        }
-                                    } else {
-                                        ink_env::debug_println!("name not found");
-                                        panic!("name not found");
-                                    }
-                                }
-                            }
-                        } else {
-                            Some(0)
-                        }
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
+                ink_env::debug_println!("not the owner");
+                Err(Error::NotOwner)
+            }
+        }
+
+        fn add_plan_unchecked(&mut self, name: Hash, plan: Plan) -> u32 {
+            let plan = Plan {
+                subscribers: 0,
+                open: true,
+                ..plan
             };
-            if let Some(value) = success {
-                if let Some(a) = self.subscription.get_mut(&name) {
-                    if let Some(_) = a.remove(&caller) {
-                        EmitEvent::<Self>::emit_event(
-                            self.env(),
-                            Unsubscribe {
-                                name,
-                                from: caller.clone(),
-                                value,
-                            },
-                        );
-                    } else {
-                        ink_env::debug_println!("failed to remove subscriber");
-                        panic!("failed to remove subscriber");
-                    }
-                } else {
-                    ink_env::debug_println!("name not found");
-                    panic!("name not found");
-                }
+            let plans = self.plans.entry(name).or_insert(Vec::new());
+            let id = plans.len() as u32;
+            plans.push(plan.clone());
+            self.env().emit_event(PlanChanged { name, id, plan });
+            id
+        }
+
+        /// Stops `plan` of `name` from taking new subscribers, current ones
+        /// keep the rate they subscribed at.
+        #[ink(message)]
+        pub fn close_plan(&mut self, name: Hash, plan: u32) -> Result<()> {
+            if self.is_owner(name)? {
+                self.close_plan_unchecked(name, plan)
             } else {
-                ink_env::debug_println!("transfer failed");
-                panic!("transfer failed");
+                ink_env::debug_println!("not the owner");
+                Err(Error::NotOwner)
             }
         }
 
+        fn close_plan_unchecked(&mut self, name: Hash, id: u32) -> Result<()> {
+            let plan = self
+                .plans
+                .get_mut(&name)
+                .and_then(|plans| plans.get_mut(id as usize))
+                .ok_or(Error::PlanNotFound)?;
+            plan.open = false;
+            let plan = plan.clone();
+            self.env().emit_event(PlanChanged { name, id, plan });
+            Ok(())
+        }
+
+        /// Returns the plans of `name`, the id of a plan is its index.
         #[ink(message)]
-        pub fn get_subscription(&self, name: Hash, from: AccountId) -> Option<SubscriberData> {
-            self.subscription
+        pub fn get_plans(&self, name: Hash) -> Vec<Plan> {
+            self.plans.get(&name).cloned().unwrap_or_default()
+        }
+
+        fn get_plan(&self, name: Hash, plan: u32) -> Option<Plan> {
+            self.plans
                 .get(&name)
-                .map(|data| data.get(&from))
-                .flatten()
+                .and_then(|plans| plans.get(plan as usize))
                 .cloned()
         }
 
-        #[ink(message)]
-        pub fn claim_fees(&mut self, name: Hash) {
-            if self.is_owner(name) {
-                self.claim_fees_unchecked(name);
-            } else {
-                ink_env::debug_println!("not the owner");
-                panic!("not the owner");
+        /// Adds the transferred balance to the subscription of `from` to
+        /// `name`. Usage accrued so far is paid to the name owner first and
+        /// accrual restarts from the current epoch.
+        #[ink(message, payable)]
+        pub fn top_up(&mut self, name: Hash, from: AccountId) -> Result<()> {
+            let payment = self.env().transferred_balance();
+            ink_env::debug_println!("top up payment: {}", payment);
+            let epoch = self.current_epoch()?;
+            let owner = self.name_owner(name)?;
+            self.top_up_at(name, from, payment, epoch, owner)
+        }
+
+        fn top_up_at(
+            &mut self,
+            name: Hash,
+            from: AccountId,
+            payment: Balance,
+            epoch: u32,
+            owner: AccountId,
+        ) -> Result<()> {
+            let d = self
+                .get_subscription(name, from)
+                .ok_or(Error::SubscriberNotFound)?;
+            let settled = Self::accrued_at(&d, epoch);
+            if settled > 0 {
+                self.transfer_balance(owner, settled)?;
             }
+            let balance = self.apply_at(name, from, settled, payment, epoch);
+            EmitEvent::<Self>::emit_event(
+                self.env(),
+                ToppedUp {
+                    name,
+                    from,
+                    value: payment,
+                    settled,
+                    balance,
+                },
+            );
+            Ok(())
         }
 
-        pub fn claim_fees_unchecked(&mut self, name: Hash) {
-            let bn = { self.get_current_epoch() };
-            let claimed = if let Some(a) = self.subscription.get(&name) {
-                let mut total = Balance::from(0u128);
-                let mut value = BTreeMap::new();
-                for (sub_id, sub_data) in a.iter() {
-                    let usage = self.get_subscription_usage(sub_data.on, bn);
-                    total = total.saturating_add(usage);
-                    let t = bn - sub_data.on;
-                    value.insert(sub_id.clone(), t);
-                }
-                Claimed { name, value, total }
-            } else {
-                ink_env::debug_println!("name not found");
-                panic!("name not found");
+        fn insert_subscriber(&mut self, name: Hash, from: AccountId, data: SubscriberData) {
+            if self.subscription.insert((name, from), data).is_none() {
+                let count = self.subscriber_count(name);
+                self.subscribers.insert((name, count), from);
+                self.positions.insert((name, from), count);
+                self.counts.insert(name, count + 1);
+            }
+        }
+
+        /// Removes `from` from the subscribers of `name`, the last subscriber
+        /// takes its position.
+        fn remove_subscriber(&mut self, name: Hash, from: AccountId, plan: u32) {
+            let d = match self.subscription.take(&(name, from)) {
+                Some(d) => d,
+                None => return,
             };
-            if let Some(a) = self.subscription.get_mut(&name) {
-                for (_, sub_data) in a.iter_mut() {
-                    (*sub_data).on = bn;
+            for key in d.keys {
+                let _ = self.keys.take(&(name, key));
+            }
+            let last = self.subscriber_count(name).saturating_sub(1);
+            if let Some(position) = self.positions.take(&(name, from)) {
+                if let Some(moved) = self.subscribers.take(&(name, last)) {
+                    if position != last {
+                        self.subscribers.insert((name, position), moved);
+                        self.positions.insert((name, moved), position);
+                    }
                 }
+            }
+            if last == 0 {
+                let _ = self.counts.take(&name);
             } else {
-                ink_env::debug_println!("name not found");
-                panic!("name not found");
+                self.counts.insert(name, last);
             }
-            match self.env().transfer(self.env().caller(), claimed.total) {
-                Err(ink_env::Error::BelowSubsistenceThreshold) => {
-                    panic!(
-                        "requested transfer would have brought contract\
-                        below subsistence threshold!"
-                    )
+            self.update_subscribers(name, plan, |n| n.saturating_sub(1));
+        }
+
+        /// Whether `key` belongs to a subscriber of `name` whose balance still
+        /// covers the current epoch, for gateways checking signed requests.
+        #[ink(message)]
+        pub fn is_authorized(&self, name: Hash, key: [u8; 32]) -> bool {
+            match self.get_current_epoch() {
+                Some(epoch) => self.is_authorized_at(name, key, epoch),
+                None => false,
+            }
+        }
+
+        fn is_authorized_at(&self, name: Hash, key: [u8; 32], epoch: u32) -> bool {
+            self.keys
+                .get(&(name, key))
+                .and_then(|from| self.get_subscription(name, *from))
+                .map(|d| !d.lapsed && (d.rate == 0 || Self::paid_until(&d) > epoch))
+                .unwrap_or(false)
+        }
+
+        /// Registers `key` for the subscription of the caller to `name`.
+        #[ink(message)]
+        pub fn add_key(&mut self, name: Hash, key: [u8; 32]) -> Result<()> {
+            self.rotate_key_unchecked(name, self.env().caller(), None, Some(key))
+        }
+
+        /// Revokes `key` from the subscription of the caller to `name`.
+        #[ink(message)]
+        pub fn revoke_key(&mut self, name: Hash, key: [u8; 32]) -> Result<()> {
+            self.rotate_key_unchecked(name, self.env().caller(), Some(key), None)
+        }
+
+        /// Replaces `old` with `new` in a single call.
+        #[ink(message)]
+        pub fn rotate_key(&mut self, name: Hash, old: [u8; 32], new: [u8; 32]) -> Result<()> {
+            self.rotate_key_unchecked(name, self.env().caller(), Some(old), Some(new))
+        }
+
+        fn rotate_key_unchecked(
+            &mut self,
+            name: Hash,
+            from: AccountId,
+            old: Option<[u8; 32]>,
+            new: Option<[u8; 32]>,
+        ) -> Result<()> {
+            let mut d = self
+                .get_subscription(name, from)
+                .ok_or(Error::SubscriberNotFound)?;
+            if let Some(old) = old {
+                let position = d
+                    .keys
+                    .iter()
+                    .position(|k| *k == old)
+                    .ok_or(Error::KeyNotFound)?;
+                d.keys.remove(position);
+            }
+            if let Some(new) = new {
+                if self.keys.contains_key(&(name, new)) && Some(new) != old {
+                    return Err(Error::KeyExists);
+                }
+                if d.keys.len() >= MAX_KEYS {
+                    return Err(Error::TooManyKeys);
                 }
-                Err(_) => panic!("transfer failed!"),
-                Ok(_) => (),
+                d.keys.push(new);
+            }
+            if let Some(old) = old {
+                let _ = self.keys.take(&(name, old));
+            }
+            if let Some(new) = new {
+                self.keys.insert((name, new), from);
             }
-            EmitEvent::<Self>::emit_event(self.env(), claimed);
+            self.subscription.insert((name, from), d);
+            EmitEvent::<Self>::emit_event(
+                self.env(),
+                KeyChanged {
+                    name,
+                    from,
+                    old,
+                    new,
+                },
+            );
+            Ok(())
         }
-    }
 
-    /// Unit tests in Rust are normally defined within such a `#[cfg(test)]`
-    /// module and test functions are marked with a `#[test]` attribute.
-    /// The below code is technically just normal Rust code.
-    #[cfg(test)]
-    mod tests {
-        /// Imports all the definitions from the outer scope so we can use them here.
-        use super::*;
+        /// Reports `units` used by `subscriber` in `epoch`, only the owner of
+        /// the name while it is not expired can do it once per subscriber and epoch, from the subscription
+        /// start up to the current epoch. The subscriber accepts the receipt
+        /// or challenges it within `CHALLENGE_WINDOW` epochs. Returns the
+        /// receipt id.
+        #[ink(message)]
+        pub fn submit_receipt(
+            &mut self,
+            name: Hash,
+            subscriber: AccountId,
+            epoch: u32,
+            units: u64,
+        ) -> Result<u32> {
+            let owner = self.caller_owner(name)?;
+            let submitted = self.current_epoch()?;
+            self.submit_receipt_at(name, subscriber, epoch, units, submitted, owner)
+        }
 
-        /// Imports `ink_lang` so we can use `#[ink::test]`.
-        use ink_lang as ink;
+        fn submit_receipt_at(
+            &mut self,
+            name: Hash,
+            subscriber: AccountId,
+            epoch: u32,
+            units: u64,
+            submitted: u32,
+            owner: AccountId,
+        ) -> Result<u32> {
+            let d = self
+                .get_subscription(name, subscriber)
+                .ok_or(Error::SubscriberNotFound)?;
+            if !d.metered {
+                return Err(Error::NotMetered);
+            }
+            if epoch < d.since || epoch > submitted {
+                ink_env::debug_println!("invalid epoch");
+                return Err(Error::InvalidEpoch);
+            }
+            if self.receipt_ids.contains_key(&(name, subscriber, epoch)) {
+                return Err(Error::ReceiptExists);
+            }
+            let id = self.receipt_count.get(&name).cloned().unwrap_or(0);
+            let receipt = Receipt {
+                subscriber,
+                owner,
+                epoch,
+                units,
+                amount: d.rate.saturating_mul(units as Balance),
+                submitted,
+                status: ReceiptStatus::Pending,
+            };
+            self.receipt_count.insert(name, id.saturating_add(1));
+            self.receipt_ids.insert((name, subscriber, epoch), id);
+            self.set_receipt(name, id, receipt);
+            Ok(id)
+        }
 
-        /// We test if the default constructor does its job.
-        #[ink::test]
-        fn default_works() {
-            let subscription = Subscription::default();
-            assert!(true);
+        fn set_receipt(&mut self, name: Hash, id: u32, receipt: Receipt) {
+            let was_open = self
+                .receipts
+                .get(&(name, id))
+                .map_or(false, |r| Self::is_open(r.status));
+            let key = (name, receipt.subscriber);
+            match (was_open, Self::is_open(receipt.status)) {
+                (false, true) => {
+                    self.open_receipts.entry(key).or_insert(Vec::new()).push(id);
+                }
+                (true, false) => {
+                    let empty = self.open_receipts.get_mut(&key).map_or(false, |ids| {
+                        ids.retain(|open| *open != id);
+                        ids.is_empty()
+                    });
+                    if empty {
+                        let _ = self.open_receipts.take(&key);
+                    }
+                }
+                _ => {}
+            }
+            self.env().emit_event(ReceiptChanged {
+                name,
+                subscriber: receipt.subscriber,
+                id,
+                amount: receipt.amount,
+                status: receipt.status,
+            });
+            self.receipts.insert((name, id), receipt);
+        }
+
+        #[ink(message)]
+        pub fn get_receipt(&self, name: Hash, id: u32) -> Option<Receipt> {
+            self.receipts.get(&(name, id)).cloned()
+        }
+
+        /// Returns how many receipts of `subscriber` are still pending or
+        /// challenged, the owner cannot terminate the subscription before
+        /// they are settled.
+        #[ink(message)]
+        pub fn open_receipts(&self, name: Hash, subscriber: AccountId) -> u32 {
+            self.open_receipts
+                .get(&(name, subscriber))
+                .map_or(0, |ids| ids.len() as u32)
+        }
+
+        fn open_receipt_ids(&self, name: Hash, subscriber: AccountId) -> Vec<u32> {
+            self.open_receipts
+                .get(&(name, subscriber))
+                .cloned()
+                .unwrap_or_default()
+        }
+
+        /// Verdicts of the challenged open receipts of `subscriber` by id.
+        fn open_verdicts(
+            &self,
+            name: Hash,
+            subscriber: AccountId,
+        ) -> Result<Vec<(u32, Option<bool>)>> {
+            let mut verdicts = Vec::new();
+            for id in self.open_receipt_ids(name, subscriber) {
+                if let Some(ReceiptStatus::Challenged(cid)) =
+                    self.get_receipt(name, id).map(|r| r.status)
+                {
+                    verdicts.push((id, self.get_verdict(name, subscriber, cid)?));
+                }
+            }
+            Ok(verdicts)
+        }
+
+        /// Settles the open receipts of a leaving subscriber. Pending ones
+        /// are charged, challenged ones are charged when their dispute was
+        /// rejected and dropped otherwise.
+        fn close_receipts_at(
+            &mut self,
+            name: Hash,
+            subscriber: AccountId,
+            verdicts: &[(u32, Option<bool>)],
+            epoch: u32,
+        ) -> Result<()> {
+            for id in self.open_receipt_ids(name, subscriber) {
+                let mut receipt = self.get_receipt(name, id).ok_or(Error::ReceiptNotFound)?;
+                let charge = match receipt.status {
+                    ReceiptStatus::Pending => true,
+                    _ => verdicts.contains(&(id, Some(false))),
+                };
+                if charge {
+                    self.charge_receipt_at(name, id, receipt, epoch)?;
+                } else {
+                    receipt.status = ReceiptStatus::Rejected;
+                    self.set_receipt(name, id, receipt);
+                }
+            }
+            Ok(())
+        }
+
+        /// Drops the open receipts of `subscriber`, an expired name has no
+        /// owner left to pay.
+        fn reject_receipts(&mut self, name: Hash, subscriber: AccountId) {
+            for id in self.open_receipt_ids(name, subscriber) {
+                if let Some(mut receipt) = self.get_receipt(name, id) {
+                    receipt.status = ReceiptStatus::Rejected;
+                    self.set_receipt(name, id, receipt);
+                }
+            }
+        }
+
+        fn is_open(status: ReceiptStatus) -> bool {
+            matches!(
+                status,
+                ReceiptStatus::Pending | ReceiptStatus::Challenged(_)
+            )
+        }
+
+        fn ensure_no_open_receipts(&self, name: Hash, subscriber: AccountId) -> Result<()> {
+            if self.open_receipts(name, subscriber) > 0 {
+                ink_env::debug_println!("open receipts");
+                return Err(Error::OpenReceipts);
+            }
+            Ok(())
+        }
+
+        /// Co-signs a pending receipt, the subscriber is charged right away.
+        #[ink(message)]
+        pub fn accept_receipt(&mut self, name: Hash, id: u32) -> Result<Balance> {
+            let epoch = self.current_epoch()?;
+            self.accept_receipt_at(name, id, self.env().caller(), epoch)
+        }
+
+        fn accept_receipt_at(
+            &mut self,
+            name: Hash,
+            id: u32,
+            caller: AccountId,
+            epoch: u32,
+        ) -> Result<Balance> {
+            let receipt = self.pending_receipt(name, id, caller)?;
+            self.charge_receipt_at(name, id, receipt, epoch)
+        }
+
+        /// Charges a pending receipt once its challenge window passed,
+        /// anyone can call it.
+        #[ink(message)]
+        pub fn finalize_receipt(&mut self, name: Hash, id: u32) -> Result<Balance> {
+            let epoch = self.current_epoch()?;
+            self.finalize_receipt_at(name, id, epoch)
+        }
+
+        fn finalize_receipt_at(&mut self, name: Hash, id: u32, epoch: u32) -> Result<Balance> {
+            let receipt = self.get_receipt(name, id).ok_or(Error::ReceiptNotFound)?;
+            if receipt.status != ReceiptStatus::Pending {
+                return Err(Error::InvalidReceiptStatus);
+            }
+            if epoch < receipt.submitted.saturating_add(CHALLENGE_WINDOW) {
+                return Err(Error::ChallengeWindowOpen);
+            }
+            self.charge_receipt_at(name, id, receipt, epoch)
+        }
+
+        /// Challenges a pending receipt within its window. The subscriber
+        /// first raises a dispute for `name` on `Dispute`, `cid` must be the
+        /// one of that dispute, which can cover several receipts. The receipt
+        /// is settled by `resolve_receipt` once judges voted.
+        #[ink(message)]
+        pub fn challenge_receipt(&mut self, name: Hash, id: u32, cid: Hash) -> Result<()> {
+            let epoch = self.current_epoch()?;
+            let caller = self.env().caller();
+            let raised = self.get_cid(name, caller)?;
+            self.challenge_receipt_at(name, id, cid, caller, epoch, raised)
+        }
+
+        /// `raised` is the cid and start epoch of the dispute `caller` has
+        /// on `Dispute` for `name`.
+        fn challenge_receipt_at(
+            &mut self,
+            name: Hash,
+            id: u32,
+            cid: Hash,
+            caller: AccountId,
+            epoch: u32,
+            raised: Option<(Hash, u32)>,
+        ) -> Result<()> {
+            let mut receipt = self.pending_receipt(name, id, caller)?;
+            if epoch >= receipt.submitted.saturating_add(CHALLENGE_WINDOW) {
+                return Err(Error::ChallengeWindowClosed);
+            }
+            match raised {
+                Some((raised_cid, _on)) if raised_cid == cid => {}
+                _ => {
+                    ink_env::debug_println!("dispute not found");
+                    return Err(Error::DisputeNotFound);
+                }
+            }
+            receipt.status = ReceiptStatus::Challenged(cid);
+            self.set_receipt(name, id, receipt);
+            Ok(())
+        }
+
+        /// Applies the verdict of `Dispute` on a challenged receipt, the
+        /// receipt is dropped when the dispute is upheld and charged
+        /// otherwise, or when no verdict was reached within
+        /// `RESOLUTION_WINDOW`. Anyone can call it.
+        #[ink(message)]
+        pub fn resolve_receipt(&mut self, name: Hash, id: u32) -> Result<Balance> {
+            let receipt = self.get_receipt(name, id).ok_or(Error::ReceiptNotFound)?;
+            let cid = match receipt.status {
+                ReceiptStatus::Challenged(cid) => cid,
+                _ => return Err(Error::InvalidReceiptStatus),
+            };
+            let verdict = self.get_verdict(name, receipt.subscriber, cid)?;
+            let epoch = self.current_epoch()?;
+            self.resolve_receipt_at(name, id, receipt, verdict, epoch)
+        }
+
+        fn resolve_receipt_at(
+            &mut self,
+            name: Hash,
+            id: u32,
+            mut receipt: Receipt,
+            verdict: Option<bool>,
+            epoch: u32,
+        ) -> Result<Balance> {
+            let deadline = receipt
+                .submitted
+                .saturating_add(CHALLENGE_WINDOW)
+                .saturating_add(RESOLUTION_WINDOW);
+            let upheld = match verdict {
+                Some(upheld) => upheld,
+                None if epoch >= deadline => false,
+                None => return Err(Error::DisputePending),
+            };
+            if upheld {
+                receipt.status = ReceiptStatus::Rejected;
+                self.set_receipt(name, id, receipt);
+                Ok(0)
+            } else {
+                self.charge_receipt_at(name, id, receipt, epoch)
+            }
+        }
+
+        fn dispute(&self) -> Result<AccountId> {
+            self.directory.get(DISPUTE).ok_or(Error::ComponentNotFound)
+        }
+
+        fn get_verdict(
+            &self,
+            name: Hash,
+            subscriber: AccountId,
+            cid: Hash,
+        ) -> Result<Option<bool>> {
+            Ok(build_call::<ink_env::DefaultEnvironment>()
+                .callee(self.dispute()?)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(GET_VERDICT))
+                        .push_arg(name)
+                        .push_arg(subscriber)
+                        .push_arg(cid),
+                )
+                .returns::<Option<bool>>()
+                .fire()
+                .map_err(|_| Error::DisputeCallFailed)?)
+        }
+
+        fn get_cid(&self, name: Hash, subscriber: AccountId) -> Result<Option<(Hash, u32)>> {
+            Ok(build_call::<ink_env::DefaultEnvironment>()
+                .callee(self.dispute()?)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(GET_CID))
+                        .push_arg(name)
+                        .push_arg(subscriber),
+                )
+                .returns::<Option<(Hash, u32)>>()
+                .fire()
+                .map_err(|_| Error::DisputeCallFailed)?)
+        }
+
+        fn pending_receipt(&self, name: Hash, id: u32, caller: AccountId) -> Result<Receipt> {
+            let receipt = self.get_receipt(name, id).ok_or(Error::ReceiptNotFound)?;
+            if receipt.subscriber != caller {
+                return Err(Error::SubscriberNotFound);
+            }
+            if receipt.status != ReceiptStatus::Pending {
+                return Err(Error::InvalidReceiptStatus);
+            }
+            Ok(receipt)
+        }
+
+        /// Pays `receipt` to the owner who submitted it, capped at the
+        /// subscriber balance.
+        fn charge_receipt_at(
+            &mut self,
+            name: Hash,
+            id: u32,
+            mut receipt: Receipt,
+            epoch: u32,
+        ) -> Result<Balance> {
+            let d = self
+                .get_subscription(name, receipt.subscriber)
+                .ok_or(Error::SubscriberNotFound)?;
+            let charged = receipt.amount.min(d.balance);
+            if charged > 0 {
+                self.transfer_balance(receipt.owner, charged)?;
+                self.apply_at(name, receipt.subscriber, charged, 0, epoch);
+            }
+            receipt.status = ReceiptStatus::Charged;
+            self.set_receipt(name, id, receipt);
+            Ok(charged)
+        }
+
+        /// Returns how many accounts are subscribed to `name`.
+        #[ink(message)]
+        pub fn subscriber_count(&self, name: Hash) -> u32 {
+            self.counts.get(&name).cloned().unwrap_or(0)
+        }
+
+        /// Returns up to `limit` subscribers of `name` from position `start`.
+        #[ink(message)]
+        pub fn get_subscribers(&self, name: Hash, start: u32, limit: u32) -> Vec<AccountId> {
+            let end = start.saturating_add(limit).min(self.subscriber_count(name));
+            (start..end)
+                .filter_map(|i| self.subscribers.get(&(name, i)).cloned())
+                .collect()
+        }
+
+        /// Refunds the whole remaining balance of the caller once `name`
+        /// expired or was unregistered, its owner can no longer be paid so
+        /// usage not settled before is not charged and open receipts are
+        /// rejected.
+        #[ink(message)]
+        pub fn reclaim(&mut self, name: Hash) -> Result<Balance> {
+            if self.registry()?.not_expired(name).is_some() {
+                ink_env::debug_println!("name still active");
+                return Err(Error::NameActive);
+            }
+            self.reclaim_unchecked(name, self.env().caller())
+        }
+
+        fn reclaim_unchecked(&mut self, name: Hash, from: AccountId) -> Result<Balance> {
+            let d = self
+                .get_subscription(name, from)
+                .ok_or(Error::SubscriberNotFound)?;
+            self.reject_receipts(name, from);
+            if d.balance > 0 {
+                self.transfer_balance(from, d.balance)?;
+            }
+            self.remove_subscriber(name, from, d.plan);
+            EmitEvent::<Self>::emit_event(
+                self.env(),
+                Refunded {
+                    name,
+                    from,
+                    usage: 0,
+                    value: d.balance,
+                },
+            );
+            Ok(d.balance)
+        }
+
+        /// Ends the subscription of `subscriber`, only the owner of the name
+        /// can do it while the name is not expired. Usage up to the current
+        /// epoch goes to the owner and the rest is refunded to the subscriber.
+        #[ink(message)]
+        pub fn terminate(&mut self, name: Hash, subscriber: AccountId) -> Result<()> {
+            let owner = self.caller_owner(name)?;
+            let epoch = self.current_epoch()?;
+            self.terminate_at(name, subscriber, epoch, owner)
+        }
+
+        fn terminate_at(
+            &mut self,
+            name: Hash,
+            from: AccountId,
+            epoch: u32,
+            owner: AccountId,
+        ) -> Result<()> {
+            let d = self
+                .get_subscription(name, from)
+                .ok_or(Error::SubscriberNotFound)?;
+            self.ensure_no_open_receipts(name, from)?;
+            let usage = Self::accrued_at(&d, epoch);
+            let value = d.balance - usage;
+            if usage > 0 {
+                self.transfer_balance(owner, usage)?;
+            }
+            if value > 0 {
+                self.transfer_balance(from, value)?;
+            }
+            self.remove_subscriber(name, from, d.plan);
+            EmitEvent::<Self>::emit_event(
+                self.env(),
+                Refunded {
+                    name,
+                    from,
+                    usage,
+                    value,
+                },
+            );
+            Ok(())
+        }
+
+        /// Ends the subscription of the caller once it is at least the
+        /// minimum period old, counted from its start. Open receipts are
+        /// settled first, see `close_receipts_at`.
+        #[ink(message)]
+        pub fn unsubscribe(&mut self, name: Hash) -> Result<()> {
+            let caller = self.env().caller();
+            let epoch = self.current_epoch()?;
+            let owner = self.name_owner(name)?;
+            let verdicts = self.open_verdicts(name, caller)?;
+            self.unsubscribe_at(name, caller, epoch, owner, &verdicts)
+        }
+
+        fn unsubscribe_at(
+            &mut self,
+            name: Hash,
+            caller: AccountId,
+            epoch: u32,
+            owner: AccountId,
+            verdicts: &[(u32, Option<bool>)],
+        ) -> Result<()> {
+            let min = self.get_min_subscriber_period();
+            let d = if let Some(d) = self.get_subscription(name, caller) {
+                d
+            } else if self.subscriber_count(name) == 0 {
+                ink_env::debug_println!("name not found");
+                return Err(Error::NameNotFound);
+            } else {
+                ink_env::debug_println!("subscriber not found");
+                return Err(Error::SubscriberNotFound);
+            };
+            ink_env::debug_println!("epoch/min/since: {:?}/{:?}/{:?}", epoch, min, d.since);
+            if epoch.saturating_sub(d.since) < min {
+                ink_env::debug_println!("min threshold not met");
+                return Err(Error::MinThresholdNotMet);
+            }
+            self.close_receipts_at(name, caller, verdicts, epoch)?;
+            let d = self
+                .get_subscription(name, caller)
+                .ok_or(Error::SubscriberNotFound)?;
+            let usage = Self::accrued_at(&d, epoch);
+            let value = d.balance - usage;
+            if value > 0 {
+                self.transfer_balance(caller.clone(), value)?;
+            }
+            if usage > 0 {
+                self.transfer_balance(owner, usage)?;
+            }
+            self.remove_subscriber(name, caller, d.plan);
+            EmitEvent::<Self>::emit_event(
+                self.env(),
+                Unsubscribe {
+                    name,
+                    from: caller.clone(),
+                    value,
+                },
+            );
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_subscription(&self, name: Hash, from: AccountId) -> Option<SubscriberData> {
+            self.subscription.get(&(name, from)).cloned()
+        }
+
+        #[ink(message)]
+        pub fn claim_fees(&mut self, name: Hash) -> Result<()> {
+            self.claim_fees_range(name, 0, u32::MAX).map(|_| ())
+        }
+
+        /// Claims the usage of the subscribers of `name` at positions
+        /// `start..start + limit` and returns the position to continue from,
+        /// so owners of large subscriber sets can claim in several calls. The
+        /// name must not be expired.
+        #[ink(message)]
+        pub fn claim_fees_range(&mut self, name: Hash, start: u32, limit: u32) -> Result<u32> {
+            self.caller_owner(name)?;
+            let bn = self.current_epoch()?;
+            self.claim_fees_at(name, start, limit, bn)
+        }
+
+        /// Pays the usage accrued up to `bn` by the subscribers in the range
+        /// to the caller and deducts it from each of them, usage is capped at
+        /// each remaining balance.
+        fn claim_fees_at(&mut self, name: Hash, start: u32, limit: u32, bn: u32) -> Result<u32> {
+            let count = self.subscriber_count(name);
+            if count == 0 {
+                ink_env::debug_println!("name not found");
+                return Err(Error::NameNotFound);
+            }
+            let end = start.saturating_add(limit).min(count);
+            let mut usages = Vec::new();
+            let mut total = Balance::from(0u128);
+            for sub_id in self.get_subscribers(name, start, limit) {
+                if let Some(sub_data) = self.subscription.get(&(name, sub_id)) {
+                    let usage = Self::accrued_at(sub_data, bn);
+                    total = total.saturating_add(usage);
+                    usages.push((sub_id, usage));
+                }
+            }
+            self.transfer_balance(self.env().caller(), total)?;
+            for (sub_id, usage) in usages {
+                self.apply_at(name, sub_id, usage, 0, bn);
+            }
+            EmitEvent::<Self>::emit_event(
+                self.env(),
+                Claimed {
+                    name,
+                    start,
+                    end: end.max(start),
+                    total,
+                },
+            );
+            Ok(end.max(start))
+        }
+    }
+
+    /// Unit tests in Rust are normally defined within such a `#[cfg(test)]`
+    /// module and test functions are marked with a `#[test]` attribute.
+    /// The below code is technically just normal Rust code.
+    #[cfg(test)]
+    mod tests {
+        /// Imports all the definitions from the outer scope so we can use them here.
+        use super::*;
+
+        /// Imports `ink_lang` so we can use `#[ink::test]`.
+        use ink_lang as ink;
+
+        /// We test if the default constructor does its job.
+        #[ink::test]
+        fn default_works() {
+            let subscription = Subscription::default();
+            assert!(true);
+        }
+
+        /// Topping up pays the accrued usage to the owner and credits the
+        /// payment, the contract keeps exactly the subscriber balance.
+        #[ink::test]
+        fn top_up_settles_usage_and_credits_balance() {
+            let accounts = default_accounts();
+            let mut subscription = new_subscription();
+            let name = Hash::from([1; 32]);
+            insert_subscriber(&mut subscription, name, accounts.bob, 100, 2);
+            set_balance(contract_id(), 100);
+            set_balance(accounts.django, 0);
+
+            assert_eq!(
+                subscription.top_up_at(name, accounts.eve, 50, 7, accounts.django),
+                Err(Error::SubscriberNotFound)
+            );
+
+            // 50 transferred on top of the 100 already held
+            set_balance(contract_id(), 150);
+            assert_eq!(
+                subscription.top_up_at(name, accounts.bob, 50, 7, accounts.django),
+                Ok(())
+            );
+            let data = subscription.get_subscription(name, accounts.bob).unwrap();
+            assert_eq!((data.balance, data.on), (135, 7));
+            assert_eq!(get_balance(accounts.django), 15);
+            assert_eq!(get_balance(contract_id()), data.balance);
+            assert_eq!(ink_env::test::recorded_events().count(), 1);
+        }
+
+        /// Usage beyond the balance is never paid out.
+        #[ink::test]
+        fn top_up_caps_settled_usage_at_balance() {
+            let accounts = default_accounts();
+            let mut subscription = new_subscription();
+            let name = Hash::from([1; 32]);
+            insert_subscriber(&mut subscription, name, accounts.bob, 10, 0);
+            set_balance(contract_id(), 30);
+            set_balance(accounts.django, 0);
+
+            assert_eq!(
+                subscription.top_up_at(name, accounts.bob, 20, 100, accounts.django),
+                Ok(())
+            );
+            let data = subscription.get_subscription(name, accounts.bob).unwrap();
+            assert_eq!((data.balance, data.on), (20, 100));
+            assert_eq!(get_balance(accounts.django), 10);
+            assert_eq!(get_balance(contract_id()), data.balance);
+        }
+
+        /// Claiming deducts the usage from every subscriber.
+        #[ink::test]
+        fn claim_fees_deducts_usage() {
+            let accounts = default_accounts();
+            let mut subscription = new_subscription();
+            let name = Hash::from([1; 32]);
+            insert_subscriber(&mut subscription, name, accounts.bob, 100, 0);
+            insert_subscriber(&mut subscription, name, accounts.eve, 5, 0);
+            set_balance(contract_id(), 105);
+            set_balance(accounts.alice, 0);
+
+            assert_eq!(subscription.claim_fees_at(name, 0, u32::MAX, 4), Ok(2));
+            let bob = subscription.get_subscription(name, accounts.bob).unwrap();
+            let eve = subscription.get_subscription(name, accounts.eve).unwrap();
+            assert_eq!((bob.balance, bob.on), (88, 4));
+            assert_eq!((eve.balance, eve.on), (0, 4));
+            assert!(eve.lapsed && !bob.lapsed);
+            assert_eq!(get_balance(accounts.alice), 17);
+            assert_eq!(get_balance(contract_id()), bob.balance + eve.balance);
+        }
+
+        /// New subscribers pick a plan and pay its minimum upfront.
+        #[ink::test]
+        fn subscribe_to_plans() {
+            let accounts = default_accounts();
+            let mut subscription = new_subscription();
+            let name = Hash::from([1; 32]);
+            assert_eq!(
+                subscription.subscribe_at(name, accounts.bob, 0, 100, 1),
+                Err(Error::PlanNotFound)
+            );
+
+            let paid = Plan {
+                rate: 5,
+                min_epochs: 10,
+                max_subscribers: 1,
+                ..Default::default()
+            };
+            assert_eq!(subscription.add_plan_unchecked(name, paid), 0);
+            assert_eq!(subscription.add_plan_unchecked(name, Plan::default()), 1);
+            assert_eq!(subscription.get_plans(name).len(), 2);
+            assert!(subscription.get_plans(Hash::from([2; 32])).is_empty());
+
+            assert_eq!(
+                subscription.subscribe_at(name, accounts.bob, 0, 49, 1),
+                Err(Error::InsufficientPayment)
+            );
+            assert_eq!(
+                subscription.subscribe_at(name, accounts.bob, 0, 50, 1),
+                Ok(())
+            );
+            let data = subscription.get_subscription(name, accounts.bob).unwrap();
+            assert_eq!((data.balance, data.plan, data.rate), (50, 0, 5));
+            assert_eq!(
+                subscription.subscribe_at(name, accounts.eve, 0, 50, 1),
+                Err(Error::PlanUnavailable)
+            );
+
+            // free tier
+            assert_eq!(
+                subscription.subscribe_at(name, accounts.eve, 1, 0, 1),
+                Ok(())
+            );
+            assert_eq!(subscription.get_plans(name)[1].subscribers, 1);
+
+            assert_eq!(subscription.close_plan_unchecked(name, 1), Ok(()));
+            assert_eq!(
+                subscription.close_plan_unchecked(name, 2),
+                Err(Error::PlanNotFound)
+            );
+            assert_eq!(
+                subscription.subscribe_at(name, accounts.frank, 1, 0, 1),
+                Err(Error::PlanUnavailable)
+            );
+            assert!(subscription.get_subscription(name, accounts.eve).is_some());
+        }
+
+        /// Accrual stops at the balance, the subscription lapses once and
+        /// a top-up reactivates it.
+        #[ink::test]
+        fn subscription_lapses_when_funds_run_out() {
+            let accounts = default_accounts();
+            let mut subscription = new_subscription();
+            let name = Hash::from([1; 32]);
+            insert_subscriber(&mut subscription, name, accounts.bob, 10, 2);
+            set_balance(contract_id(), 10);
+            set_balance(accounts.django, 0);
+
+            assert_eq!(subscription.paid_until_epoch(name, accounts.bob), Some(5));
+            assert_eq!(subscription.paid_until_epoch(name, accounts.eve), None);
+            assert_eq!(subscription.balance_of_at(name, accounts.bob, 4), Some(4));
+            assert_eq!(subscription.balance_of_at(name, accounts.bob, 9), Some(0));
+            assert_eq!(subscription.balance_of_at(name, accounts.eve, 4), None);
+
+            assert_eq!(
+                subscription.settle_at(name, accounts.bob, 4, accounts.django),
+                Ok(6)
+            );
+            assert!(
+                !subscription
+                    .get_subscription(name, accounts.bob)
+                    .unwrap()
+                    .lapsed
+            );
+            assert_eq!(subscription.paid_until_epoch(name, accounts.bob), Some(5));
+            assert_eq!(
+                subscription.settle_at(name, accounts.bob, 9, accounts.django),
+                Ok(4)
+            );
+            assert!(
+                subscription
+                    .get_subscription(name, accounts.bob)
+                    .unwrap()
+                    .lapsed
+            );
+            assert_eq!(
+                subscription.settle_at(name, accounts.bob, 12, accounts.django),
+                Ok(0)
+            );
+            assert_eq!(get_balance(accounts.django), 10);
+            assert_eq!(get_balance(contract_id()), 0);
+            // lapsed only once
+            assert_eq!(ink_env::test::recorded_events().count(), 1);
+
+            set_balance(contract_id(), 6);
+            assert_eq!(
+                subscription.top_up_at(name, accounts.bob, 6, 12, accounts.django),
+                Ok(())
+            );
+            let data = subscription.get_subscription(name, accounts.bob).unwrap();
+            assert!(!data.lapsed);
+            assert_eq!(subscription.paid_until_epoch(name, accounts.bob), Some(14));
+        }
+
+        /// Settling moves the accrual checkpoint but not the start the
+        /// minimum period is counted from.
+        #[ink::test]
+        fn settle_does_not_delay_unsubscribe() {
+            let accounts = default_accounts();
+            let mut subscription = new_subscription();
+            let name = Hash::from([1; 32]);
+            insert_subscriber(&mut subscription, name, accounts.bob, 30, 1);
+            set_balance(contract_id(), 30);
+            set_balance(accounts.bob, 0);
+            set_balance(accounts.django, 0);
+
+            assert_eq!(
+                subscription.settle_at(name, accounts.bob, 2, accounts.django),
+                Ok(3)
+            );
+            assert_eq!(
+                subscription.unsubscribe_at(name, accounts.bob, 2, accounts.django, &[]),
+                Err(Error::MinThresholdNotMet)
+            );
+            assert_eq!(
+                subscription.settle_at(name, accounts.bob, 3, accounts.django),
+                Ok(3)
+            );
+            let data = subscription.get_subscription(name, accounts.bob).unwrap();
+            assert_eq!((data.on, data.since), (3, 1));
+            assert_eq!(
+                subscription.unsubscribe_at(name, accounts.bob, 3, accounts.django, &[]),
+                Ok(())
+            );
+            assert_eq!(get_balance(accounts.bob), 24);
+            assert_eq!(get_balance(accounts.django), 6);
+            assert_eq!(subscription.get_subscription(name, accounts.bob), None);
+        }
+
+        /// Subscribers of an expired name get their whole balance back.
+        #[ink::test]
+        fn reclaim_refunds_balance() {
+            let accounts = default_accounts();
+            let mut subscription = new_subscription();
+            let name = Hash::from([1; 32]);
+            insert_subscriber(&mut subscription, name, accounts.bob, 30, 0);
+            insert_subscriber(&mut subscription, name, accounts.eve, 20, 0);
+            set_balance(contract_id(), 50);
+            set_balance(accounts.bob, 0);
+
+            assert_eq!(subscription.reclaim_unchecked(name, accounts.bob), Ok(30));
+            assert_eq!(
+                subscription.reclaim_unchecked(name, accounts.bob),
+                Err(Error::SubscriberNotFound)
+            );
+            assert_eq!(get_balance(accounts.bob), 30);
+            let eve = subscription.get_subscription(name, accounts.eve).unwrap();
+            assert_eq!(get_balance(contract_id()), eve.balance);
+
+            assert_eq!(subscription.reclaim_unchecked(name, accounts.eve), Ok(20));
+            assert_eq!(get_balance(contract_id()), 0);
+            assert_eq!(subscription.subscriber_count(name), 0);
+        }
+
+        /// Owner messages need a record from `not_expired`, which the
+        /// registry no longer returns once the name expired.
+        #[ink::test]
+        fn expired_owner_cannot_manage_subscribers() {
+            let accounts = default_accounts();
+            assert_eq!(
+                Subscription::owned_by(Some((accounts.django, 0, 10)), accounts.django),
+                Ok(accounts.django)
+            );
+            assert_eq!(
+                Subscription::owned_by(Some((accounts.django, 0, 10)), accounts.bob),
+                Err(Error::NotOwner)
+            );
+            assert_eq!(
+                Subscription::owned_by(None, accounts.django),
+                Err(Error::NameNotFound)
+            );
+        }
+
+        /// Termination splits the balance between usage and refund.
+        #[ink::test]
+        fn terminate_refunds_pro_rata() {
+            let accounts = default_accounts();
+            let mut subscription = new_subscription();
+            let name = Hash::from([1; 32]);
+            let plan = Plan {
+                rate: 3,
+                ..Default::default()
+            };
+            assert_eq!(subscription.add_plan_unchecked(name, plan), 0);
+            assert_eq!(
+                subscription.subscribe_at(name, accounts.bob, 0, 30, 2),
+                Ok(())
+            );
+            insert_subscriber(&mut subscription, name, accounts.eve, 10, 2);
+            set_balance(contract_id(), 40);
+            set_balance(accounts.bob, 0);
+            set_balance(accounts.django, 0);
+
+            assert_eq!(
+                subscription.terminate_at(name, accounts.bob, 6, accounts.django),
+                Ok(())
+            );
+            assert_eq!(get_balance(accounts.django), 12);
+            assert_eq!(get_balance(accounts.bob), 18);
+            assert!(subscription.get_subscription(name, accounts.bob).is_none());
+            assert_eq!(subscription.get_plans(name)[0].subscribers, 0);
+            let eve = subscription.get_subscription(name, accounts.eve).unwrap();
+            assert_eq!(get_balance(contract_id()), eve.balance);
+            assert_eq!(
+                subscription.terminate_at(name, accounts.bob, 6, accounts.django),
+                Err(Error::SubscriberNotFound)
+            );
+        }
+
+        /// Claims cover a range of positions, removals keep positions dense.
+        #[ink::test]
+        fn claim_fees_by_range() {
+            let accounts = default_accounts();
+            let mut subscription = new_subscription();
+            let name = Hash::from([1; 32]);
+            assert_eq!(
+                subscription.claim_fees_at(name, 0, 2, 4),
+                Err(Error::NameNotFound)
+            );
+            insert_subscriber(&mut subscription, name, accounts.bob, 100, 0);
+            insert_subscriber(&mut subscription, name, accounts.eve, 100, 0);
+            insert_subscriber(&mut subscription, name, accounts.frank, 100, 0);
+            set_balance(contract_id(), 300);
+            set_balance(accounts.alice, 0);
+
+            assert_eq!(subscription.subscriber_count(name), 3);
+            // the first page stops before frank and returns where to resume
+            let next = subscription.claim_fees_at(name, 0, 2, 4).unwrap();
+            assert_eq!(next, 2);
+            assert_eq!(get_balance(accounts.alice), 24);
+            let state = |subscription: &Subscription, who| {
+                let d = subscription.get_subscription(name, who).unwrap();
+                (d.balance, d.on)
+            };
+            assert_eq!(state(&subscription, accounts.bob), (88, 4));
+            assert_eq!(state(&subscription, accounts.eve), (88, 4));
+            assert_eq!(state(&subscription, accounts.frank), (100, 0));
+
+            let next = subscription.claim_fees_at(name, next, 2, 4).unwrap();
+            assert_eq!(next, 3);
+            assert_eq!(state(&subscription, accounts.frank), (88, 4));
+            assert_eq!(get_balance(accounts.alice), 36);
+            // past the end nothing is claimed twice
+            assert_eq!(subscription.claim_fees_at(name, next, 2, 4), Ok(3));
+            assert_eq!(get_balance(accounts.alice), 36);
+            assert_eq!(get_balance(contract_id()), 3 * 88);
+
+            subscription.remove_subscriber(name, accounts.bob, 0);
+            assert_eq!(subscription.subscriber_count(name), 2);
+            assert_eq!(
+                subscription.get_subscribers(name, 0, 10),
+                vec![accounts.frank, accounts.eve]
+            );
+            subscription.remove_subscriber(name, accounts.eve, 0);
+            subscription.remove_subscriber(name, accounts.frank, 0);
+            assert_eq!(subscription.subscriber_count(name), 0);
+            assert!(subscription.get_subscribers(name, 0, 10).is_empty());
+        }
+
+        /// Keys authorize requests while the subscription is paid.
+        #[ink::test]
+        fn keys_authorize_active_subscribers() {
+            let accounts = default_accounts();
+            let mut subscription = new_subscription();
+            let name = Hash::from([1; 32]);
+            let (k1, k2, k3) = ([1; 32], [2; 32], [3; 32]);
+            assert_eq!(
+                subscription.rotate_key_unchecked(name, accounts.bob, None, Some(k1)),
+                Err(Error::SubscriberNotFound)
+            );
+            insert_subscriber(&mut subscription, name, accounts.bob, 30, 0);
+            insert_subscriber(&mut subscription, name, accounts.eve, 30, 0);
+
+            assert_eq!(
+                subscription.rotate_key_unchecked(name, accounts.bob, None, Some(k1)),
+                Ok(())
+            );
+            assert_eq!(
+                subscription.rotate_key_unchecked(name, accounts.eve, None, Some(k1)),
+                Err(Error::KeyExists)
+            );
+            assert_eq!(subscription.paid_until_epoch(name, accounts.bob), Some(10));
+            assert!(subscription.is_authorized_at(name, k1, 9));
+            assert!(!subscription.is_authorized_at(name, k1, 10));
+            assert!(!subscription.is_authorized_at(name, k2, 0));
+            assert!(!subscription.is_authorized_at(Hash::from([2; 32]), k1, 0));
+
+            assert_eq!(
+                subscription.rotate_key_unchecked(name, accounts.bob, Some(k2), Some(k3)),
+                Err(Error::KeyNotFound)
+            );
+            assert_eq!(
+                subscription.rotate_key_unchecked(name, accounts.bob, Some(k1), Some(k2)),
+                Ok(())
+            );
+            assert!(!subscription.is_authorized_at(name, k1, 0));
+            assert!(subscription.is_authorized_at(name, k2, 0));
+            assert_eq!(
+                subscription
+                    .get_subscription(name, accounts.bob)
+                    .unwrap()
+                    .keys,
+                vec![k2]
+            );
+
+            assert_eq!(
+                subscription.rotate_key_unchecked(name, accounts.bob, Some(k2), None),
+                Ok(())
+            );
+            assert!(!subscription.is_authorized_at(name, k2, 0));
+
+            for i in 0..MAX_KEYS {
+                let key = [10 + i as u8; 32];
+                assert_eq!(
+                    subscription.rotate_key_unchecked(name, accounts.eve, None, Some(key)),
+                    Ok(())
+                );
+            }
+            assert_eq!(
+                subscription.rotate_key_unchecked(name, accounts.eve, None, Some(k3)),
+                Err(Error::TooManyKeys)
+            );
+            subscription.remove_subscriber(name, accounts.eve, 0);
+            assert!(!subscription.is_authorized_at(name, [10; 32], 0));
+            assert_eq!(
+                subscription.rotate_key_unchecked(name, accounts.bob, None, Some([10; 32])),
+                Ok(())
+            );
+        }
+
+        /// Receipts are charged when accepted or after the challenge window,
+        /// challenged ones follow the dispute verdict.
+        #[ink::test]
+        fn metered_receipts() {
+            let accounts = default_accounts();
+            let mut subscription = new_subscription();
+            let name = Hash::from([1; 32]);
+            let plan = Plan {
+                rate: 2,
+                metered: true,
+                ..Default::default()
+            };
+            assert_eq!(subscription.add_plan_unchecked(name, plan), 0);
+            assert_eq!(
+                subscription.subscribe_at(name, accounts.bob, 0, 100, 1),
+                Ok(())
+            );
+            insert_subscriber(&mut subscription, name, accounts.eve, 100, 1);
+            set_balance(contract_id(), 200);
+            set_balance(accounts.django, 0);
+
+            assert_eq!(
+                subscription.submit_receipt_at(name, accounts.eve, 1, 5, 2, accounts.django),
+                Err(Error::NotMetered)
+            );
+            assert_eq!(
+                subscription.balance_of_at(name, accounts.bob, 50),
+                Some(100)
+            );
+            assert_eq!(
+                subscription.submit_receipt_at(name, accounts.bob, 1, 10, 2, accounts.django),
+                Ok(0)
+            );
+            assert_eq!(
+                subscription.submit_receipt_at(name, accounts.bob, 2, 15, 3, accounts.django),
+                Ok(1)
+            );
+            assert_eq!(
+                subscription.submit_receipt_at(name, accounts.bob, 3, 90, 4, accounts.django),
+                Ok(2)
+            );
+
+            // one receipt per epoch, from the start up to the submission
+            assert_eq!(
+                subscription.submit_receipt_at(name, accounts.bob, 0, 5, 4, accounts.django),
+                Err(Error::InvalidEpoch)
+            );
+            assert_eq!(
+                subscription.submit_receipt_at(name, accounts.bob, 5, 5, 4, accounts.django),
+                Err(Error::InvalidEpoch)
+            );
+            assert_eq!(
+                subscription.submit_receipt_at(name, accounts.bob, 1, 5, 4, accounts.django),
+                Err(Error::ReceiptExists)
+            );
+
+            // open receipts keep the owner from terminating
+            assert_eq!(subscription.open_receipts(name, accounts.bob), 3);
+            assert_eq!(
+                subscription.terminate_at(name, accounts.bob, 4, accounts.django),
+                Err(Error::OpenReceipts)
+            );
+
+            // accepted by the subscriber only
+            assert_eq!(
+                subscription.accept_receipt_at(name, 0, accounts.eve, 2),
+                Err(Error::SubscriberNotFound)
+            );
+            assert_eq!(
+                subscription.accept_receipt_at(name, 0, accounts.bob, 2),
+                Ok(20)
+            );
+            assert_eq!(
+                subscription.accept_receipt_at(name, 0, accounts.bob, 2),
+                Err(Error::InvalidReceiptStatus)
+            );
+
+            // finalized once the window passed
+            assert_eq!(
+                subscription.finalize_receipt_at(name, 1, 4),
+                Err(Error::ChallengeWindowOpen)
+            );
+            assert_eq!(subscription.finalize_receipt_at(name, 1, 5), Ok(30));
+
+            // challenged with the dispute of the subscriber, upheld dispute
+            // drops it
+            let cid = Hash::from([9; 32]);
+            assert_eq!(
+                subscription.challenge_receipt_at(name, 2, cid, accounts.bob, 6, Some((cid, 5))),
+                Err(Error::ChallengeWindowClosed)
+            );
+            for raised in [None, Some((Hash::from([7; 32]), 5))].iter() {
+                assert_eq!(
+                    subscription.challenge_receipt_at(name, 2, cid, accounts.bob, 5, *raised),
+                    Err(Error::DisputeNotFound)
+                );
+            }
+            assert_eq!(
+                subscription.challenge_receipt_at(name, 2, cid, accounts.bob, 5, Some((cid, 5))),
+                Ok(())
+            );
+            let receipt = subscription.get_receipt(name, 2).unwrap();
+            assert_eq!(receipt.status, ReceiptStatus::Challenged(cid));
+            assert_eq!(
+                subscription.finalize_receipt_at(name, 2, 10),
+                Err(Error::InvalidReceiptStatus)
+            );
+            assert_eq!(
+                subscription.resolve_receipt_at(name, 2, receipt.clone(), Some(true), 10),
+                Ok(0)
+            );
+            assert_eq!(
+                subscription.get_receipt(name, 2).unwrap().status,
+                ReceiptStatus::Rejected
+            );
+
+            // a dispute raised before the receipt covers it too, without a
+            // verdict it is charged after the resolution window, capped at
+            // the balance
+            assert_eq!(
+                subscription.submit_receipt_at(name, accounts.bob, 4, 90, 5, accounts.django),
+                Ok(3)
+            );
+            let cid = Hash::from([8; 32]);
+            assert_eq!(
+                subscription.challenge_receipt_at(name, 3, cid, accounts.bob, 5, Some((cid, 3))),
+                Ok(())
+            );
+            let receipt = subscription.get_receipt(name, 3).unwrap();
+            assert_eq!(
+                subscription.resolve_receipt_at(name, 3, receipt.clone(), None, 16),
+                Err(Error::DisputePending)
+            );
+            assert_eq!(
+                subscription.resolve_receipt_at(name, 3, receipt, None, 17),
+                Ok(50)
+            );
+            let bob = subscription.get_subscription(name, accounts.bob).unwrap();
+            assert_eq!(bob.balance, 0);
+            assert!(bob.lapsed);
+            assert_eq!(get_balance(accounts.django), 100);
+            assert_eq!(get_balance(contract_id()), 100);
+            assert_eq!(subscription.open_receipts(name, accounts.bob), 0);
+
+            // never charged once the subscription is gone
+            assert_eq!(
+                subscription.submit_receipt_at(name, accounts.bob, 5, 1, 6, accounts.django),
+                Ok(4)
+            );
+            subscription.remove_subscriber(name, accounts.bob, 0);
+            assert_eq!(
+                subscription.finalize_receipt_at(name, 4, 8),
+                Err(Error::SubscriberNotFound)
+            );
+            assert_eq!(
+                subscription.get_receipt(name, 4).unwrap().status,
+                ReceiptStatus::Pending
+            );
+        }
+
+        /// Leaving settles the open receipts instead of waiting for them.
+        #[ink::test]
+        fn exit_settles_open_receipts() {
+            let accounts = default_accounts();
+            let mut subscription = new_subscription();
+            let name = Hash::from([1; 32]);
+            let plan = Plan {
+                rate: 2,
+                metered: true,
+                ..Default::default()
+            };
+            assert_eq!(subscription.add_plan_unchecked(name, plan), 0);
+            for from in [accounts.bob, accounts.eve].iter() {
+                assert_eq!(subscription.subscribe_at(name, *from, 0, 100, 1), Ok(()));
+            }
+            set_balance(contract_id(), 200);
+            set_balance(accounts.django, 0);
+            set_balance(accounts.bob, 0);
+            set_balance(accounts.eve, 0);
+
+            let cid = Hash::from([9; 32]);
+            for (epoch, units) in [(1, 10), (2, 15), (3, 5)].iter() {
+                let submitted = epoch + 1;
+                let id = subscription
+                    .submit_receipt_at(
+                        name,
+                        accounts.bob,
+                        *epoch,
+                        *units,
+                        submitted,
+                        accounts.django,
+                    )
+                    .unwrap();
+                if id > 0 {
+                    assert_eq!(
+                        subscription.challenge_receipt_at(
+                            name,
+                            id,
+                            cid,
+                            accounts.bob,
+                            submitted,
+                            Some((cid, 3))
+                        ),
+                        Ok(())
+                    );
+                }
+            }
+            assert_eq!(
+                subscription.submit_receipt_at(name, accounts.eve, 1, 10, 2, accounts.django),
+                Ok(3)
+            );
+
+            // pending and rejected disputes are charged, the rest dropped
+            assert_eq!(
+                subscription.unsubscribe_at(
+                    name,
+                    accounts.bob,
+                    4,
+                    accounts.django,
+                    &[(1, Some(false)), (2, None)]
+                ),
+                Ok(())
+            );
+            let status = |subscription: &Subscription, id| {
+                subscription.get_receipt(name, id).unwrap().status
+            };
+            assert_eq!(status(&subscription, 0), ReceiptStatus::Charged);
+            assert_eq!(status(&subscription, 1), ReceiptStatus::Charged);
+            assert_eq!(status(&subscription, 2), ReceiptStatus::Rejected);
+            assert_eq!(subscription.open_receipts(name, accounts.bob), 0);
+            assert_eq!(get_balance(accounts.django), 50);
+            assert_eq!(get_balance(accounts.bob), 50);
+
+            // nothing is charged once the name expired
+            assert_eq!(subscription.reclaim_unchecked(name, accounts.eve), Ok(100));
+            assert_eq!(status(&subscription, 3), ReceiptStatus::Rejected);
+            assert_eq!(subscription.open_receipts(name, accounts.eve), 0);
+            assert_eq!(get_balance(accounts.eve), 100);
+            assert_eq!(get_balance(contract_id()), 0);
+        }
+
+        fn new_subscription() -> Subscription {
+            let accounts = default_accounts();
+            Subscription::new(ink_env::call::FromAccountId::from_account_id(
+                accounts.django,
+            ))
+        }
+
+        fn insert_subscriber(
+            subscription: &mut Subscription,
+            name: Hash,
+            from: AccountId,
+            balance: Balance,
+            on: BlockNumber,
+        ) {
+            subscription.insert_subscriber(
+                name,
+                from,
+                SubscriberData {
+                    balance,
+                    on,
+                    since: on,
+                    plan: 0,
+                    rate: 3,
+                    lapsed: false,
+                    keys: Vec::new(),
+                    metered: false,
+                },
+            );
+        }
+
+        fn contract_id() -> AccountId {
+            ink_env::account_id::<ink_env::DefaultEnvironment>().unwrap_or([0x0; 32].into())
+        }
+
+        fn set_balance(account_id: AccountId, balance: Balance) {
+            ink_env::test::set_account_balance::<ink_env::DefaultEnvironment>(account_id, balance)
+                .expect("Cannot set account balance");
+        }
+
+        fn get_balance(account_id: AccountId) -> Balance {
+            ink_env::test::get_account_balance::<ink_env::DefaultEnvironment>(account_id)
+                .expect("Cannot get account balance")
+        }
+
+        fn default_accounts() -> ink_env::test::DefaultAccounts<ink_env::DefaultEnvironment> {
+            ink_env::test::default_accounts::<ink_env::DefaultEnvironment>()
+                .expect("Off-chain environment should have been initialized already")
         }
     }
 }
@@ -15,9 +15,9 @@ const endowment = 1230000000000n;
 const gasLimit = 100000n * 1000000n;
 const pay_zero = 0n;
 
-const ep_metaData = JSON.parse(readFileSync('../target/ink/epoch_proxy/metadata.json', 'utf8'));
+const ep_metaData = JSON.parse(readFileSync('../target/ink/proxy/metadata.json', 'utf8'));
 const epoch_metaData = JSON.parse(readFileSync('../target/ink/epoch/metadata.json', 'utf8'));
-const rp_metaData = JSON.parse(readFileSync('../target/ink/registry_proxy/metadata.json', 'utf8'));
+const rp_metaData = JSON.parse(readFileSync('../target/ink/proxy/metadata.json', 'utf8'));
 const reg_metaData = JSON.parse(readFileSync('../target/ink/registry/metadata.json', 'utf8'));
 
 const deploy = async () => {